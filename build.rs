@@ -7,9 +7,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     config
         .type_attribute(
             "NewWorker",
-            "#[derive(Queryable, Insertable, AsChangeset, Associations, Serialize, Deserialize)]",
+            "#[derive(Queryable, Associations, Serialize, Deserialize)]",
         )
         .type_attribute("NewWorker", "#[table_name = \"workers\"]")
+        // Metadata saved by older workers doesn't have it
+        .field_attribute("NewWorker.fuzzers", "#[serde(default)]")
+        .field_attribute("NewWorker.labels", "#[serde(default)]")
+        // Heartbeat only, never saved to cwd. Stored fields are inserted through collector's NewWorkerRow
+        .field_attribute("NewWorker.running_tasks", "#[serde(skip)]")
         // Worker
         .type_attribute("Worker", "#[derive(Queryable, Identifiable, Associations)]")
         .type_attribute("Worker", "#[table_name = \"workers\"]")
@@ -31,6 +36,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Corpus
         .type_attribute("Corpus", "#[derive(Queryable, Identifiable, Associations)]")
         .type_attribute("Corpus", "#[table_name = \"corpora\"]")
+        // NewCorpusSnapshot (CorpusSnapshot with limited fields)
+        .type_attribute("NewCorpusSnapshot", "#[derive(Queryable, Insertable, Associations)]")
+        .type_attribute("NewCorpusSnapshot", "#[table_name = \"corpus_snapshots\"]")
+        // CorpusSnapshot
+        .type_attribute("CorpusSnapshot", "#[derive(Queryable, Identifiable, Associations)]")
+        .type_attribute("CorpusSnapshot", "#[table_name = \"corpus_snapshots\"]")
         // NewCrash (Crash with limited fields)
         .type_attribute(
             "NewCrash",
@@ -47,6 +58,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Crash
         .type_attribute("Crash", "#[derive(Queryable, Identifiable, Associations)]")
         .type_attribute("Crash", "#[table_name = \"crashes\"]")
+        // NewCrashVariant (Validation of a crash against a build variant)
+        .type_attribute(
+            "NewCrashVariant",
+            "#[derive(Queryable, Insertable, AsChangeset, Associations)]",
+        )
+        .type_attribute("NewCrashVariant", "#[table_name = \"crash_variants\"]")
+        .type_attribute("NewCrashVariant", "#[belongs_to(Crash)]")
+        .type_attribute("NewCrashVariant", "#[changeset_options(treat_none_as_null=\"true\")]")
+        // CrashVariant
+        .type_attribute("CrashVariant", "#[derive(Queryable, Identifiable, Associations)]")
+        .type_attribute("CrashVariant", "#[table_name = \"crash_variants\"]")
+        .type_attribute("CrashVariant", "#[belongs_to(Crash)]")
+        // NewArtifact (Artifact without id & time)
+        .type_attribute("NewArtifact", "#[derive(Queryable, Insertable)]")
+        .type_attribute("NewArtifact", "#[table_name = \"artifacts\"]")
+        // Artifact
+        .type_attribute("Artifact", "#[derive(Queryable, Identifiable)]")
+        .type_attribute("Artifact", "#[table_name = \"artifacts\"]")
+        // ArtifactInfo (Artifact without content)
+        .type_attribute("ArtifactInfo", "#[derive(Queryable)]")
         // WorkerTask (Worker Task)
         .type_attribute("WorkerTask", "#[derive(Queryable, Identifiable, Associations)]")
         .type_attribute("WorkerTask", "#[table_name = \"worker_tasks\"]")
@@ -58,6 +89,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // WorkerTask (Worker Task)
         .type_attribute("WorkerTaskFull", "#[derive(Queryable, Associations)]")
         // .type_attribute("WorkerTaskFull", "#[table_name = \"worker_tasks\"]")
+        // NewWorkerTaskLog (Output line of fuzzer process)
+        .type_attribute("NewWorkerTaskLog", "#[derive(Queryable, Insertable, Associations)]")
+        .type_attribute("NewWorkerTaskLog", "#[table_name = \"worker_task_logs\"]")
+        .type_attribute("NewWorkerTaskLog", "#[belongs_to(WorkerTask)]")
+        // WorkerTaskLog
+        .type_attribute("WorkerTaskLog", "#[derive(Queryable, Identifiable, Associations)]")
+        .type_attribute("WorkerTaskLog", "#[table_name = \"worker_task_logs\"]")
+        .type_attribute("WorkerTaskLog", "#[belongs_to(WorkerTask)]")
         // NewFuzzStat (FuzzStat without time field)
         .type_attribute(
             "NewFuzzStat",
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE corpus_snapshots (
+	id SERIAL PRIMARY KEY,
+	name VARCHAR(100) NOT NULL,
+	label VARCHAR(100) NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+	UNIQUE(name, label)
+);
+
+CREATE TABLE corpus_snapshot_entries (
+	id SERIAL PRIMARY KEY,
+	snapshot_id INTEGER NOT NULL REFERENCES corpus_snapshots(id) ON DELETE CASCADE,
+	content bytea NOT NULL,
+	checksum VARCHAR(64) NOT NULL,
+	worker_task_id INTEGER REFERENCES worker_tasks(id) ON DELETE SET NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+	UNIQUE(snapshot_id, checksum)
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE corpora DROP COLUMN coverage;
+ALTER TABLE corpora DROP COLUMN driver;
+ALTER TABLE corpora DROP COLUMN size;
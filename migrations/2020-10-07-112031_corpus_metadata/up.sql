@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE corpora ADD COLUMN size INT NOT NULL DEFAULT 0;
+ALTER TABLE corpora ADD COLUMN driver VARCHAR;
+ALTER TABLE corpora ADD COLUMN coverage INT;
+UPDATE corpora SET size = length(content);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE worker_tasks ADD COLUMN restarts INT NOT NULL DEFAULT 0;
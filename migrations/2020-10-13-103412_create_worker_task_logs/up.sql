@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE worker_task_logs (
+	id SERIAL PRIMARY KEY,
+	worker_task_id INT NOT NULL REFERENCES worker_tasks(id) ON DELETE CASCADE,
+	stream VARCHAR NOT NULL,
+	line VARCHAR NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+CREATE INDEX worker_task_logs_worker_task_id_idx ON worker_task_logs (worker_task_id, id);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE artifacts (
+	id SERIAL PRIMARY KEY,
+	name VARCHAR NOT NULL,
+	version VARCHAR NOT NULL,
+	checksum VARCHAR NOT NULL,
+	content BYTEA NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+	UNIQUE (name, version)
+);
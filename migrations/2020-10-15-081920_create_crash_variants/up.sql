@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE crash_variants (
+	id SERIAL PRIMARY KEY,
+	crash_id INT NOT NULL REFERENCES crashes(id) ON DELETE CASCADE,
+	variant VARCHAR NOT NULL,
+	verified BOOLEAN NOT NULL DEFAULT FALSE,
+	output VARCHAR,
+	created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+	UNIQUE(crash_id, variant)
+);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE workers
+	DROP COLUMN docker,
+	DROP COLUMN podman,
+	DROP COLUMN kvm,
+	DROP COLUMN disk_free,
+	DROP COLUMN kernel,
+	DROP COLUMN arch,
+	DROP COLUMN fuzzers;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE workers
+	ADD COLUMN docker BOOLEAN,
+	ADD COLUMN podman BOOLEAN,
+	ADD COLUMN kvm BOOLEAN,
+	ADD COLUMN disk_free INT,
+	ADD COLUMN kernel VARCHAR,
+	ADD COLUMN arch VARCHAR,
+	ADD COLUMN fuzzers TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sys_stats
+	DROP COLUMN disk_total,
+	DROP COLUMN disk_free;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE sys_stats
+	ADD COLUMN disk_total INT,
+	ADD COLUMN disk_free INT;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE workers
+	ADD COLUMN labels TEXT[] NOT NULL DEFAULT '{}';
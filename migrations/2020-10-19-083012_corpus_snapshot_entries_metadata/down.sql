@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE corpus_snapshot_entries DROP COLUMN coverage;
+ALTER TABLE corpus_snapshot_entries DROP COLUMN driver;
+ALTER TABLE corpus_snapshot_entries DROP COLUMN size;
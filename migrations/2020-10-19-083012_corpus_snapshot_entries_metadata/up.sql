@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE corpus_snapshot_entries ADD COLUMN size INT NOT NULL DEFAULT 0;
+ALTER TABLE corpus_snapshot_entries ADD COLUMN driver VARCHAR;
+ALTER TABLE corpus_snapshot_entries ADD COLUMN coverage INT;
+UPDATE corpus_snapshot_entries SET size = length(content);
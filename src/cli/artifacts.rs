@@ -0,0 +1,39 @@
+use std::error::Error;
+use std::path::Path;
+
+use clap::ArgMatches;
+use tracing::{debug, info};
+
+use crate::common::artifacts::{get_artifacts, upload_artifact};
+use crate::common::xpc::get_orchestrator_client;
+
+pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    debug!("Creating interface client");
+    let mut client = get_orchestrator_client().await?;
+
+    match args.subcommand() {
+        ("upload", Some(sub_matches)) => {
+            let name = sub_matches.value_of("name").unwrap().to_owned();
+            let version = sub_matches.value_of("version").unwrap().to_owned();
+            let path = sub_matches.value_of("path").unwrap();
+
+            upload_artifact(name, version, Path::new(path), &mut client).await?;
+            info!("Successfully uploaded artifact");
+        }
+        ("list", Some(sub_matches)) => {
+            let name = sub_matches.value_of("name").map(|s| s.to_owned());
+            let artifacts = get_artifacts(name, None, &mut client).await?;
+
+            let artifacts_heading = vec!["ID", "Name", "Version", "Checksum", "Created At"];
+            let mut artifacts_vec = Vec::new();
+            for a in artifacts.iter() {
+                artifacts_vec.push(super::formatter::format_artifact(a));
+            }
+
+            super::print_results(artifacts_heading, artifacts_vec);
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
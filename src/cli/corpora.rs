@@ -6,8 +6,13 @@ use clap::ArgMatches;
 use tracing::{debug, info};
 use tokio::task;
 
-use crate::common::corpora::{delete_corpus, download_corpus_to_disk, upload_corpus_from_disk};
+use crate::common::corpora::{
+    delete_corpus, delete_corpus_snapshot, diff_corpus_snapshot, download_filtered_corpus_to_disk,
+    export_corpus_to_archive, get_corpus_snapshots, import_corpus_from_archive, restore_corpus_snapshot,
+    snapshot_corpus, upload_corpus_from_disk,
+};
 use crate::common::xpc::get_orchestrator_client;
+use crate::xpc;
 
 pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
     debug!("Creating interface client");
@@ -27,7 +32,7 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
                 let label = sub_matches.value_of("label").unwrap().to_owned();
                 let mut local_client = client.clone(); // Create new client clones to pass
                 task_set.spawn_local(async move {
-                    upload_corpus_from_disk(Path::new(file_path.as_str()), label, None, &mut local_client).await
+                    upload_corpus_from_disk(Path::new(file_path.as_str()), label, None, None, &mut local_client).await
                 });
             }
             task_set.await;
@@ -36,21 +41,24 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
             debug!("Downloading corpus");
             let path = sub_matches.value_of("path").expect("Path to save corpus not provided");
 
-            let mut latest = None;
+            let mut filter_corpus = xpc::FilterCorpus {
+                label: sub_matches.value_of("label").expect("Label not provided").to_owned(),
+                created_after: prost_types::Timestamp::from(SystemTime::UNIX_EPOCH),
+                driver: sub_matches.value_of("driver").map(|s| s.to_owned()),
+                with_coverage: Some(sub_matches.is_present("with_coverage")),
+                ..Default::default()
+            };
             if let Some(l) = sub_matches.value_of("latest") {
-                latest = Some(l.parse::<i64>()?);
+                filter_corpus.latest = Some(l.parse::<i64>()?);
+            }
+            if let Some(s) = sub_matches.value_of("min_size") {
+                filter_corpus.min_size = Some(s.parse::<i32>()?);
+            }
+            if let Some(s) = sub_matches.value_of("max_size") {
+                filter_corpus.max_size = Some(s.parse::<i32>()?);
             }
 
-            let corpora = download_corpus_to_disk(
-                sub_matches.value_of("label").expect("Label not provided").to_owned(),
-                None,
-                None,
-                latest,
-                SystemTime::UNIX_EPOCH,
-                Path::new(path),
-                &mut client,
-            )
-            .await?;
+            let corpora = download_filtered_corpus_to_disk(filter_corpus, Path::new(path), &mut client).await?;
 
             info!("Successfully downloaded {} corpus to {}", corpora, path);
         }
@@ -62,6 +70,71 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
 
             let _ = delete_corpus(label, None, None, None, SystemTime::UNIX_EPOCH, &mut client).await?;
         }
+        ("export", Some(sub_matches)) => {
+            let label = sub_matches.value_of("label").expect("Label not provided").to_owned();
+            let path = sub_matches.value_of("path").expect("Path to save archive not provided");
+
+            let corpora = export_corpus_to_archive(label, Path::new(path), &mut client).await?;
+            info!("Successfully exported {} corpus to {}", corpora, path);
+        }
+        ("import", Some(sub_matches)) => {
+            let label = sub_matches.value_of("label").expect("Label not provided").to_owned();
+            let path = sub_matches.value_of("path").expect("Path to archive not provided");
+
+            let corpora = import_corpus_from_archive(label, Path::new(path), &mut client).await?;
+            info!("Successfully imported {} corpus from {}", corpora, path);
+        }
+        ("snapshot", Some(sub_matches)) => {
+            let label = sub_matches.value_of("label").expect("Label not provided").to_owned();
+            let name = sub_matches.value_of("name").expect("Name not provided").to_owned();
+
+            let snapshot = snapshot_corpus(label, name, &mut client).await?;
+            info!("Successfully created corpus snapshot with id {}", snapshot.id);
+        }
+        ("snapshots", Some(sub_matches)) => {
+            let label = sub_matches.value_of("label").map(|s| s.to_owned());
+            let snapshots = get_corpus_snapshots(label, None, &mut client).await?;
+
+            let snapshots_heading = vec!["ID", "Name", "Label", "Created At"];
+            let mut snapshots_vec = Vec::new();
+            for s in snapshots.iter() {
+                snapshots_vec.push(super::formatter::format_corpus_snapshot(s));
+            }
+
+            super::print_results(snapshots_heading, snapshots_vec);
+        }
+        ("restore", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").expect("No ID provided").parse::<i32>()?;
+
+            restore_corpus_snapshot(id, sub_matches.is_present("merge"), &mut client).await?;
+            info!("Successfully restored corpus snapshot {}", id);
+        }
+        ("diff", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").expect("No ID provided").parse::<i32>()?;
+
+            let mut against_id = None;
+            if let Some(a) = sub_matches.value_of("against") {
+                against_id = Some(a.parse::<i32>()?);
+            }
+
+            let diff = diff_corpus_snapshot(id, against_id, &mut client).await?;
+
+            let mut diff_vec = Vec::new();
+            for checksum in diff.added.iter() {
+                diff_vec.push(vec!["+".to_owned(), checksum.clone()]);
+            }
+            for checksum in diff.removed.iter() {
+                diff_vec.push(vec!["-".to_owned(), checksum.clone()]);
+            }
+
+            super::print_results(vec!["", "Checksum"], diff_vec);
+            info!("{} added, {} removed", diff.added.len(), diff.removed.len());
+        }
+        ("delete-snapshot", Some(sub_matches)) => {
+            let id = sub_matches.value_of("id").expect("No ID provided").parse::<i32>()?;
+
+            delete_corpus_snapshot(id, &mut client).await?;
+        }
         // Listing all tasks
         _ => {}
     }
@@ -7,7 +7,7 @@ use tracing::{debug, info};
 
 use crate::common::{
     cli::parse_volume_map_settings,
-    crashes::{download_crashes, download_crashes_to_disk, update_crash},
+    crashes::{download_crashes, download_crashes_to_disk, update_crash, upload_crash_variants},
     profiles::construct_profile,
     tasks::get_task,
     xpc::get_orchestrator_client,
@@ -88,9 +88,19 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
             for crash in crashes.iter() {
                 debug!("Validating crash {:?}", crash);
                 tokio::fs::write(crash_path, &crash.content).await?;
-                let (output, verified) = validator.validate_crash(crash_path).await?;
+                let (mut output, mut verified) = validator.validate_crash(crash_path).await?;
+
+                let variants = validator.validate_crash_variants(crash_path).await;
+                if let Some(variant) = variants.iter().find(|v| v.verified) {
+                    verified = true;
+                    if output.is_none() {
+                        output = Some(variant.output.clone());
+                    }
+                }
+
                 // Set duplicate to None as you need to revalidate
                 update_crash(crash.id, verified, output, None, &mut client).await?;
+                upload_crash_variants(crash.id, variants, &mut client).await?;
                 tokio::fs::remove_file(crash_path).await?;
             }
         }
@@ -1,4 +1,7 @@
-use crate::models::{Corpus, Task};
+use std::time::{Duration, SystemTime};
+
+use crate::models::{ArtifactInfo, Corpus, CorpusSnapshot, Task};
+use crate::utils::get_human_dt;
 
 pub fn format_task<'a>(t: &'a Task) -> Vec<String> {
     vec![
@@ -10,14 +13,40 @@ pub fn format_task<'a>(t: &'a Task) -> Vec<String> {
 }
 
 pub fn _format_corpus<'a>(c: &'a Corpus) -> Vec<String> {
-    vec![format!("{}", c.id), c.checksum.clone(), c.label.clone()]
+    vec![
+        format!("{}", c.id),
+        c.checksum.clone(),
+        c.label.clone(),
+        format!("{}", c.size),
+        format!("{:?}", c.driver),
+        format!("{:?}", c.coverage),
+    ]
 }
 
 pub fn _print_corpora(corpora: Vec<Corpus>) {
-    let corpora_heading = vec!["ID", "Checksum", "Label"];
+    let corpora_heading = vec!["ID", "Checksum", "Label", "Size", "Driver", "Coverage"];
     let mut corpora_vec = Vec::new();
     for c in corpora.iter() {
         corpora_vec.push(_format_corpus(c));
     }
     super::print_results(corpora_heading, corpora_vec);
 }
+
+pub fn format_artifact<'a>(a: &'a ArtifactInfo) -> Vec<String> {
+    vec![
+        format!("{}", a.id),
+        a.name.clone(),
+        a.version.clone(),
+        a.checksum.clone(),
+        get_human_dt(SystemTime::UNIX_EPOCH + Duration::from_secs(a.created_at.seconds as u64)),
+    ]
+}
+
+pub fn format_corpus_snapshot<'a>(s: &'a CorpusSnapshot) -> Vec<String> {
+    vec![
+        format!("{}", s.id),
+        s.name.clone(),
+        s.label.clone(),
+        get_human_dt(SystemTime::UNIX_EPOCH + Duration::from_secs(s.created_at.seconds as u64)),
+    ]
+}
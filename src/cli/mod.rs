@@ -6,6 +6,7 @@ use prettytable::{Row, Table};
 
 use crate::common::cli::parse_global_settings;
 
+mod artifacts;
 mod corpora;
 mod crashes;
 mod formatter;
@@ -45,6 +46,9 @@ async fn main_loop(arg_matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
         ("profile", Some(sub_matches)) => {
             profile::cli(sub_matches).await?;
         }
+        ("artifacts", Some(sub_matches)) => {
+            artifacts::cli(sub_matches).await?;
+        }
         _ => {}
     }
     Ok(())
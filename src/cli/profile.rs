@@ -6,13 +6,13 @@ use tracing::{debug, error, info, warn};
 use tokio::{
     signal::unix::{signal, SignalKind},
     sync::broadcast,
-    sync::oneshot,
+    sync::{oneshot, watch},
     task::LocalSet,
 };
 
 use crate::common::cli::parse_volume_map_settings;
-use crate::executor::{self, ExecutorConfig};
-use crate::fuzz_driver::{self, FuzzConfig};
+use crate::executor::{self, output_logger::read_line, ExecutorConfig};
+use crate::fuzz_driver::{self, DriverState, FuzzConfig};
 use crate::utils::fs::read_file;
 
 pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
@@ -46,7 +46,7 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
             // Spawn off stdout output
             let mut stdout_reader = executor.get_stdout_reader().unwrap();
             local_set.spawn_local(async move {
-                while let Ok(Some(line)) = stdout_reader.next_line().await {
+                while let Ok(Some(line)) = read_line(&mut stdout_reader).await {
                     info!("Stdout: {}", line);
                 }
             });
@@ -54,7 +54,7 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
             // Spawn off stderr output
             let mut stderr_reader = executor.get_stderr_reader().unwrap();
             local_set.spawn_local(async move {
-                while let Ok(Some(line)) = stderr_reader.next_line().await {
+                while let Ok(Some(line)) = read_line(&mut stderr_reader).await {
                     warn!("Stderr: {}", line);
                 }
             });
@@ -97,10 +97,11 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
             // Fake tx, will not be used
             let (tx, rx) = oneshot::channel::<u8>();
             let (death_tx, _) = oneshot::channel::<u8>();
+            let (state_tx, _) = watch::channel(DriverState::default());
 
             let mut stream = signal(SignalKind::interrupt())?;
             tokio::select! {
-                result = driver.start(rx, death_tx) => {
+                result = driver.start(rx, death_tx, state_tx) => {
                     error!("Fuzz driver exited first, something is wrong");
                     if let Err(e) = result {
                         error!("Cause: {}", e);
@@ -4,8 +4,11 @@ use std::path::Path;
 use clap::ArgMatches;
 use tracing::{debug, error, info};
 use tonic::Request;
+use crate::common::intervals::CLI_LOGS_FOLLOW_INTERVAL;
 use crate::common::profiles::{construct_profile_from_disk, write_profile_to_disk};
+use crate::common::worker_tasks::download_worker_task_logs;
 use crate::common::xpc::get_orchestrator_client;
+use crate::executor::output_logger::STDERR_LOG;
 use crate::models::{NewTask, PatchTask};
 use crate::xpc::FilterTask;
 
@@ -128,6 +131,37 @@ pub async fn cli(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
                 error!("Got no task");
             }
         }
+        ("logs", Some(sub_matches)) => {
+            let worker_task_id = sub_matches
+                .value_of("worker_task_id")
+                .expect("No worker task ID provided")
+                .parse::<i32>()?;
+            let limit = match sub_matches.value_of("limit") {
+                Some(limit) => Some(limit.parse::<i64>()?),
+                None => None,
+            };
+
+            let mut after_id = None;
+            let mut interval = tokio::time::interval(CLI_LOGS_FOLLOW_INTERVAL);
+            loop {
+                let logs = download_worker_task_logs(worker_task_id, after_id, limit, &mut client).await?;
+                for log in logs.iter() {
+                    if log.stream == STDERR_LOG {
+                        eprintln!("{}", log.line);
+                    } else {
+                        println!("{}", log.line);
+                    }
+                }
+                if let Some(log) = logs.last() {
+                    after_id = Some(log.id);
+                }
+
+                if sub_matches.is_present("follow") == false {
+                    break;
+                }
+                interval.tick().await;
+            }
+        }
         _ => {}
     }
 
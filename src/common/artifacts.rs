@@ -0,0 +1,130 @@
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, info};
+use tokio::{fs, task};
+use tonic::{transport::channel::Channel, Request};
+use uuid::Uuid;
+
+use crate::common::constants::WORKER_ARTIFACT_CACHE;
+use crate::common::xpc::{get_orchestrator_client, report_status};
+use crate::executor::ArtifactConfig;
+use crate::models::{Artifact, ArtifactInfo, NewArtifact};
+use crate::utils::{checksum, fs::mkdir_p};
+use crate::xpc::{self, orchestrator_client::OrchestratorClient};
+
+pub const ARTIFACT_ARCHIVE_EXT: &str = "tar.zst";
+pub const DEFAULT_ARTIFACT_CACHE: &str = ".fuzzy_artifacts";
+
+// Downloaded artifacts are kept here by checksum, so that tasks sharing them download only once
+pub fn set_artifact_cache(artifact_cache: &str) {
+    debug!("Setting artifact cache to {}", artifact_cache);
+    env::set_var(WORKER_ARTIFACT_CACHE, artifact_cache);
+}
+
+pub fn get_artifact_cache() -> PathBuf {
+    PathBuf::from(env::var(WORKER_ARTIFACT_CACHE).unwrap_or_else(|_| DEFAULT_ARTIFACT_CACHE.to_owned()))
+}
+
+/// Archives contents of a directory, or a single file, into a tar.zst bundle
+fn create_artifact_archive(path: &Path) -> io::Result<Vec<u8>> {
+    let mut archive = tar::Builder::new(zstd::Encoder::new(Vec::new(), 0)?);
+    if path.is_dir() {
+        archive.append_dir_all(".", path)?;
+    } else {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid path {:?}", path)))?;
+        archive.append_path_with_name(path, name)?;
+    }
+    archive.into_inner()?.finish()
+}
+
+fn unpack_artifact_archive(archive_path: &Path, cwd: &Path) -> io::Result<()> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(archive_path)?)?);
+    // Executable bits of targets need to survive
+    archive.set_preserve_permissions(true);
+    archive.unpack(cwd)
+}
+
+pub async fn upload_artifact(
+    name: String,
+    version: String,
+    path: &Path,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<(), Box<dyn Error>> {
+    debug!("Archiving {:?} as artifact {}:{}", path, name, version);
+    let archive_path = path.to_path_buf();
+    let content = task::spawn_blocking(move || create_artifact_archive(&archive_path)).await??;
+
+    let new_artifact = NewArtifact {
+        name,
+        version,
+        checksum: checksum(&content),
+        content,
+    };
+    client.submit_artifact(Request::new(new_artifact)).await?;
+    Ok(())
+}
+
+pub async fn get_artifacts(
+    name: Option<String>,
+    version: Option<String>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<Vec<ArtifactInfo>, Box<dyn Error>> {
+    let filter_artifact = xpc::FilterArtifact { name, version };
+    let response = report_status(client.get_artifacts(Request::new(filter_artifact)).await).await?;
+    Ok(response.into_inner().data)
+}
+
+pub async fn download_artifact(id: i32, client: &mut OrchestratorClient<Channel>) -> Result<Artifact, Box<dyn Error>> {
+    let response = report_status(client.fetch_artifact(Request::new(xpc::Id { value: id })).await).await?;
+    let artifact = response.into_inner();
+    if checksum(&artifact.content) != artifact.checksum {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Checksum mismatch for artifact {}:{}", artifact.name, artifact.version),
+        )));
+    }
+    Ok(artifact)
+}
+
+/// Unpacks referred artifact into cwd, it is downloaded only if not already present in cache
+pub async fn setup_artifact(config: &ArtifactConfig, cwd: &Path) -> Result<(), Box<dyn Error>> {
+    let mut client = get_orchestrator_client().await?;
+
+    // Without a version, latest one is picked
+    let artifact = get_artifacts(Some(config.name.clone()), config.version.clone(), &mut client)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No artifact {} with version {:?}", config.name, config.version),
+            )
+        })?;
+
+    let cache = get_artifact_cache();
+    mkdir_p(&cache).await?;
+    let cached_path = cache.join(format!("{}.{}", artifact.checksum, ARTIFACT_ARCHIVE_EXT));
+
+    if cached_path.exists() == false {
+        info!("Downloading artifact {}:{}", artifact.name, artifact.version);
+        let content = download_artifact(artifact.id, &mut client).await?.content;
+
+        // Rename is atomic, so a partial download never looks cached even with concurrent tasks
+        let partial_path = cache.join(format!("{}.partial", Uuid::new_v4()));
+        fs::write(&partial_path, content).await?;
+        fs::rename(&partial_path, &cached_path).await?;
+    } else {
+        debug!("Using cached artifact {:?}", cached_path);
+    }
+
+    let cwd = cwd.to_path_buf();
+    task::spawn_blocking(move || unpack_artifact_archive(&cached_path, &cwd)).await??;
+    Ok(())
+}
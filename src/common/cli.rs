@@ -3,18 +3,33 @@ use std::env;
 use clap::ArgMatches;
 use tracing::{debug, error};
 
-use crate::common::constants::{FUZZY_CA_CERT, FUZZY_CLIENT_PEM, FUZZY_CONNECT_URL};
-use crate::common::executors::{set_container_volume_map, validate_container_volume_map};
+use crate::common::artifacts::{set_artifact_cache, DEFAULT_ARTIFACT_CACHE};
+use crate::common::constants::{
+    FUZZY_CA_CERT, FUZZY_CLIENT_PEM, FUZZY_CONNECT_URL, WORKER_ARTIFACT_CACHE, WORKER_CGROUP_ROOT,
+    WORKER_CONTAINER_VOLUME_MAP, WORKER_MIN_DISK_FREE, WORKER_OUTBOX_DIR, WORKER_RESERVED_CPUS, WORKER_RESERVED_MEMORY,
+    WORKER_SHUTDOWN_TIMEOUT,
+};
+use crate::common::corpora::set_corpus_cache;
+use crate::common::executors::{
+    set_cgroup_root, set_container_volume_map, set_min_disk_free, set_reserved_capacity, set_shutdown_timeout,
+    validate_container_volume_map, validate_reserved_capacity, DEFAULT_CGROUP_ROOT, DEFAULT_MIN_DISK_FREE,
+    DEFAULT_RESERVED_CPUS, DEFAULT_RESERVED_MEMORY, DEFAULT_SHUTDOWN_TIMEOUT,
+};
+use crate::common::outbox::{set_outbox_dir, DEFAULT_OUTBOX_DIR};
 use crate::common::xpc::{set_ca_crt, set_connect_url, set_worker_pem};
 
 pub fn parse_volume_map_settings(sub_matches: &ArgMatches) {
-    // Set up volume map after verifying
-    if let Some(container_volume) = sub_matches.value_of("container_volume") {
-        if let Err(e) = validate_container_volume_map(container_volume) {
+    // Set up volume map after verifying, it can also come from worker config through environment
+    let container_volume = sub_matches
+        .value_of("container_volume")
+        .map(|v| v.to_owned())
+        .or_else(|| env::var(WORKER_CONTAINER_VOLUME_MAP).ok());
+    if let Some(container_volume) = container_volume {
+        if let Err(e) = validate_container_volume_map(&container_volume) {
             error!("Invalid volume map provided: {}", e);
             panic!("Exiting");
         } else {
-            set_container_volume_map(container_volume);
+            set_container_volume_map(&container_volume);
         }
     } else {
         error!("Volume map is not provided");
@@ -55,3 +70,71 @@ pub fn parse_global_settings(sub_matches: &ArgMatches) {
     let worker_pem_path = get_arg(sub_matches, "worker_pem", FUZZY_CLIENT_PEM, "worker.pem");
     set_worker_pem(&worker_pem_path);
 }
+
+pub fn parse_cgroup_settings(sub_matches: &ArgMatches) {
+    let cgroup_root = get_arg(sub_matches, "cgroup_root", WORKER_CGROUP_ROOT, DEFAULT_CGROUP_ROOT);
+    set_cgroup_root(&cgroup_root);
+}
+
+pub fn parse_artifact_settings(sub_matches: &ArgMatches) {
+    let artifact_cache = get_arg(
+        sub_matches,
+        "artifact_cache",
+        WORKER_ARTIFACT_CACHE,
+        DEFAULT_ARTIFACT_CACHE,
+    );
+    set_artifact_cache(&artifact_cache);
+}
+
+// Default depends on volume map, so it is only set when provided
+pub fn parse_corpus_cache_settings(sub_matches: &ArgMatches) {
+    if let Some(corpus_cache) = sub_matches.value_of("corpus_cache") {
+        set_corpus_cache(corpus_cache);
+    }
+}
+
+pub fn parse_disk_settings(sub_matches: &ArgMatches) {
+    let min_disk_free = get_arg(
+        sub_matches,
+        "min_disk_free",
+        WORKER_MIN_DISK_FREE,
+        DEFAULT_MIN_DISK_FREE,
+    );
+    set_min_disk_free(&min_disk_free);
+}
+
+pub fn parse_reservation_settings(sub_matches: &ArgMatches) {
+    let reserved_cpus = get_arg(
+        sub_matches,
+        "reserved_cpus",
+        WORKER_RESERVED_CPUS,
+        DEFAULT_RESERVED_CPUS,
+    );
+    let reserved_memory = get_arg(
+        sub_matches,
+        "reserved_memory",
+        WORKER_RESERVED_MEMORY,
+        DEFAULT_RESERVED_MEMORY,
+    );
+    if let Err(e) = validate_reserved_capacity(&reserved_cpus, &reserved_memory) {
+        error!("Invalid reservation provided: {}", e);
+        panic!("Exiting");
+    } else {
+        set_reserved_capacity(&reserved_cpus, &reserved_memory);
+    }
+}
+
+pub fn parse_shutdown_settings(sub_matches: &ArgMatches) {
+    let shutdown_timeout = get_arg(
+        sub_matches,
+        "shutdown_timeout",
+        WORKER_SHUTDOWN_TIMEOUT,
+        DEFAULT_SHUTDOWN_TIMEOUT,
+    );
+    set_shutdown_timeout(&shutdown_timeout);
+}
+
+pub fn parse_outbox_settings(sub_matches: &ArgMatches) {
+    let outbox_dir = get_arg(sub_matches, "outbox_dir", WORKER_OUTBOX_DIR, DEFAULT_OUTBOX_DIR);
+    set_outbox_dir(&outbox_dir);
+}
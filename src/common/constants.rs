@@ -3,6 +3,16 @@ pub const WORKER_CONNECT_CACERT_ENV_KEY: &str = "FUZZY_WORKER_CONNECT_CA_CERT_PA
 pub const WORKER_CONNECT_WORKERPEM_ENV_KEY: &str = "FUZZY_WORKER_CONNECT_WORKER_PEM_PATH";
 
 pub const WORKER_CONTAINER_VOLUME_MAP: &str = "FUZZY_CONTAINER_VOLUME_MAP";
+pub const WORKER_CGROUP_ROOT: &str = "FUZZY_CGROUP_ROOT";
+pub const WORKER_ARTIFACT_CACHE: &str = "FUZZY_ARTIFACT_CACHE";
+pub const WORKER_OUTBOX_DIR: &str = "FUZZY_OUTBOX_DIR";
+pub const WORKER_MIN_DISK_FREE: &str = "FUZZY_MIN_DISK_FREE";
+pub const WORKER_CORPUS_CACHE: &str = "FUZZY_CORPUS_CACHE";
+pub const WORKER_CONFIG: &str = "FUZZY_WORKER_CONFIG";
+pub const WORKER_RESERVED_CPUS: &str = "FUZZY_RESERVED_CPUS";
+pub const WORKER_RESERVED_MEMORY: &str = "FUZZY_RESERVED_MEMORY";
+pub const WORKER_SHUTDOWN_TIMEOUT: &str = "FUZZY_SHUTDOWN_TIMEOUT";
+pub const WORKER_UUID: &str = "FUZZY_WORKER_UUID";
 
 pub const FUZZY_CONNECT_URL: &str = "FUZZY_CONNECT_URL";
 pub const FUZZY_CA_CERT: &str = "FUZZY_CA_CERT";
@@ -1,41 +1,94 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
-use std::io;
-use std::path::Path;
+use std::fs::File;
+use std::fs::Permissions;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
-use tokio::fs;
+use tokio::{fs, sync::Mutex, task};
 use tonic::{transport::channel::Channel, Request};
+use uuid::Uuid;
 
-use crate::models::{Corpus, NewCorpus};
+use crate::common::constants::WORKER_CORPUS_CACHE;
+use crate::common::executors::{extract_contraint_volume_map, get_container_volume_map};
+use crate::common::xpc::report_status;
+use crate::models::{Corpus, CorpusSnapshot, NewCorpus, NewCorpusSnapshot};
 use crate::utils::{checksum, fs::read_file};
 use crate::xpc::{self, orchestrator_client::OrchestratorClient};
 
 pub const CORPUS_FILE_EXT: &str = "fuzzy";
+pub const CORPUS_ARCHIVE_MANIFEST: &str = "manifest.yaml";
+pub const DEFAULT_CORPUS_CACHE: &str = ".fuzzy_corpus";
+const CORPUS_CACHE_FETCH_BATCH: usize = 100;
+const CORPUS_CACHE_FILE_MODE: u32 = 0o444;
+// Corpus entries asked from master at a time while exporting
+const CORPUS_ARCHIVE_PAGE_SIZE: i64 = 100;
+
+lazy_static! {
+    // Entries are pruned once nothing links them, so caching & linking of an entry has to happen
+    // without a prune in between
+    static ref CORPUS_CACHE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+// Downloaded corpus is kept here by checksum & hardlinked into tasks, so that tasks sharing a label
+// download each entry only once
+pub fn set_corpus_cache(corpus_cache: &str) {
+    debug!("Setting corpus cache to {}", corpus_cache);
+    env::set_var(WORKER_CORPUS_CACHE, corpus_cache);
+}
+
+/// Defaults to a directory on volume map, as hardlinks only work within same filesystem
+pub fn get_corpus_cache() -> Result<PathBuf, Box<dyn Error>> {
+    if let Ok(corpus_cache) = env::var(WORKER_CORPUS_CACHE) {
+        return Ok(PathBuf::from(corpus_cache));
+    }
+    let (_, mapped_path) = extract_contraint_volume_map(get_container_volume_map()?.as_str());
+    Ok(mapped_path.join(DEFAULT_CORPUS_CACHE))
+}
 
 // Corpus related utilities
 pub async fn upload_corpus_from_disk(
     file_path: &Path,
     label: String,
     worker_task_id: Option<i32>,
+    driver: Option<String>,
     client: &mut OrchestratorClient<Channel>,
 ) -> Result<(), Box<dyn Error>> {
     debug!("Trying to upload {:?} to corpus", file_path);
     let content = read_file(file_path).await?;
+    upload_corpus(content, label, worker_task_id, driver, client).await
+}
 
+pub async fn upload_corpus(
+    content: Vec<u8>,
+    label: String,
+    worker_task_id: Option<i32>,
+    driver: Option<String>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<(), Box<dyn Error>> {
+    let new_corpus = new_corpus(content, label, worker_task_id, driver);
+    client.submit_corpus(Request::new(new_corpus)).await?;
+    Ok(())
+}
+
+pub fn new_corpus(content: Vec<u8>, label: String, worker_task_id: Option<i32>, driver: Option<String>) -> NewCorpus {
     // Generate checksum
     let checksum = checksum(&content);
 
-    // Send request
-    let new_corpus = NewCorpus {
+    NewCorpus {
+        size: content.len() as i32,
         content,
         checksum,
         label,
         worker_task_id,
-    };
-
-    client.submit_corpus(Request::new(new_corpus)).await?;
-    Ok(())
+        driver,
+    }
 }
 
 pub async fn download_corpus(
@@ -57,8 +110,16 @@ pub async fn download_corpus(
         not_worker_task_id,
         for_worker_task_id,
         latest,
+        ..Default::default()
     };
-    let response = client.get_corpus(Request::new(filter_corpus)).await?;
+    download_filtered_corpus(filter_corpus, client).await
+}
+
+pub async fn download_filtered_corpus(
+    filter_corpus: xpc::FilterCorpus,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<Vec<Corpus>, Box<dyn Error>> {
+    let response = report_status(client.get_corpus(Request::new(filter_corpus)).await).await?;
     Ok(response.into_inner().data)
 }
 
@@ -78,6 +139,7 @@ pub async fn delete_corpus(
         not_worker_task_id,
         for_worker_task_id,
         latest,
+        ..Default::default()
     };
     let _ = client.delete_corpus(Request::new(filter_corpus)).await?;
     Ok(())
@@ -101,7 +163,21 @@ pub async fn download_corpus_to_disk(
         client,
     )
     .await?;
+    write_corpus_to_disk(&corpora, dir).await?;
+    Ok(corpora.len())
+}
 
+pub async fn download_filtered_corpus_to_disk(
+    filter_corpus: xpc::FilterCorpus,
+    dir: &Path,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<usize, Box<dyn Error>> {
+    let corpora = download_filtered_corpus(filter_corpus, client).await?;
+    write_corpus_to_disk(&corpora, dir).await?;
+    Ok(corpora.len())
+}
+
+async fn create_corpus_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
     // Check if exists, if not create. If exists and not a directory, Err
     if dir.exists() == false {
         fs::create_dir_all(dir).await?;
@@ -111,14 +187,523 @@ pub async fn download_corpus_to_disk(
             format!("{:?} is not a directory to download corpus", dir),
         )));
     }
+    Ok(())
+}
+
+pub fn get_corpus_file_path(dir: &Path, checksum: &str) -> PathBuf {
+    let mut file_path = dir.join(checksum);
+    file_path.set_extension(CORPUS_FILE_EXT);
+    file_path
+}
+
+pub async fn write_corpus_to_disk(corpora: &Vec<Corpus>, dir: &Path) -> Result<(), Box<dyn Error>> {
+    create_corpus_dir(dir).await?;
 
     for corpus in corpora.iter() {
-        let mut file_path = dir.clone().join(&corpus.checksum);
-        file_path.set_extension(CORPUS_FILE_EXT);
-        fs::write(file_path, &corpus.content).await?;
+        fs::write(get_corpus_file_path(dir, &corpus.checksum), &corpus.content).await?;
     }
 
     debug!("Written {} corpus files to {:?}", corpora.len(), dir);
 
-    Ok(corpora.len())
+    Ok(())
+}
+
+/// Same as download_corpus_to_disk, but only entries missing from worker's corpus cache are fetched
+/// and everything is hardlinked from cache into dir
+pub async fn download_corpus_to_disk_cached(
+    label: String,
+    not_worker_task_id: Option<i32>,
+    for_worker_task_id: Option<i32>,
+    limit: Option<i64>,
+    created_after: SystemTime,
+    dir: &Path,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<usize, Box<dyn Error>> {
+    let filter_corpus = xpc::FilterCorpus {
+        label: label.clone(),
+        created_after: prost_types::Timestamp::from(created_after),
+        not_worker_task_id,
+        for_worker_task_id,
+        latest: limit,
+        without_content: Some(true),
+        ..Default::default()
+    };
+    let listed = download_filtered_corpus(filter_corpus, client).await?;
+    let checksums: Vec<&str> = listed.iter().map(|c| c.checksum.as_str()).collect();
+
+    let cache = get_corpus_cache()?;
+    create_corpus_dir(&cache).await?;
+    create_corpus_dir(dir).await?;
+
+    // Hardlinks only work within a filesystem. Copies out of cache would look unused to pruning, so
+    // cache is bypassed altogether then
+    let cached = fs::metadata(&cache).await?.dev() == fs::metadata(dir).await?.dev();
+    if cached == false {
+        debug!(
+            "Corpus cache {:?} is on another filesystem than {:?}, not using it",
+            cache, dir
+        );
+    } else {
+        // Entries linked into a task are never pruned, so whatever is cached already is kept from here on
+        let _lock = CORPUS_CACHE_LOCK.lock().await;
+        link_cached_corpus(&cache, dir, &checksums).await?;
+    }
+
+    let missing: Vec<String> = checksums
+        .iter()
+        .filter(|c| get_corpus_file_path(dir, c).exists() == false)
+        .map(|c| c.to_string())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect();
+    debug!("Fetching {} of {} listed corpus", missing.len(), listed.len());
+
+    // Fetching happens without lock so that tasks don't wait on each other's downloads. Fetched entries
+    // are cached & linked together under lock, so that they are never pruned in between
+    for batch in missing.chunks(CORPUS_CACHE_FETCH_BATCH) {
+        let filter_corpus = xpc::FilterCorpus {
+            label: label.clone(),
+            created_after: prost_types::Timestamp::from(SystemTime::UNIX_EPOCH),
+            checksums: batch.to_vec(),
+            ..Default::default()
+        };
+        let fetched = download_filtered_corpus(filter_corpus, client).await?;
+        if cached == false {
+            write_corpus_to_disk(&fetched, dir).await?;
+            continue;
+        }
+
+        let _lock = CORPUS_CACHE_LOCK.lock().await;
+        for corpus in fetched.iter() {
+            write_corpus_to_cache(corpus, &cache).await?;
+        }
+        let batch: Vec<&str> = batch.iter().map(|c| c.as_str()).collect();
+        link_cached_corpus(&cache, dir, &batch).await?;
+    }
+
+    if cached {
+        let _lock = CORPUS_CACHE_LOCK.lock().await;
+        prune_corpus_cache(&cache).await?;
+    }
+
+    debug!("Synced {} corpus files into {:?}", listed.len(), dir);
+    Ok(listed.len())
+}
+
+async fn link_cached_corpus(cache: &Path, dir: &Path, checksums: &[&str]) -> Result<(), Box<dyn Error>> {
+    for checksum in checksums.iter() {
+        let cached_path = get_corpus_file_path(cache, checksum);
+        let file_path = get_corpus_file_path(dir, checksum);
+        if cached_path.exists() == false || file_path.exists() {
+            continue;
+        }
+        fs::hard_link(&cached_path, &file_path).await?;
+    }
+    Ok(())
+}
+
+/// Cached files are read-only, as every task linking an entry shares same inode & a fuzzer rewriting
+/// it in place would change it for all of them
+async fn write_corpus_to_cache(corpus: &Corpus, cache: &Path) -> Result<(), Box<dyn Error>> {
+    if checksum(&corpus.content) != corpus.checksum {
+        warn!("Not caching corpus {} as checksum doesn't match", corpus.id);
+        return Ok(());
+    }
+    // Rename is atomic, so a partial write never looks cached
+    let partial_path = cache.join(format!("{}.partial", Uuid::new_v4()));
+    fs::write(&partial_path, &corpus.content).await?;
+    fs::set_permissions(&partial_path, Permissions::from_mode(CORPUS_CACHE_FILE_MODE)).await?;
+    fs::rename(&partial_path, get_corpus_file_path(cache, &corpus.checksum)).await?;
+    Ok(())
+}
+
+/// Entries not linked into any task directory anymore are removed, tasks remove their corpus on teardown
+async fn prune_corpus_cache(cache: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut pruned = 0;
+    let mut entries = fs::read_dir(cache).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() && metadata.nlink() == 1 {
+            fs::remove_file(entry.path()).await?;
+            pruned += 1;
+        }
+    }
+    debug!("Pruned {} unused entries from corpus cache", pruned);
+    Ok(pruned)
+}
+
+/// Let master know about new coverage a batch of corpus contributed
+pub async fn update_corpus_coverage(
+    ids: Vec<i32>,
+    coverage: i32,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<(), Box<dyn Error>> {
+    debug!("Updating coverage of {} corpus to {}", ids.len(), coverage);
+    let corpus_coverage = xpc::CorpusCoverage { ids, coverage };
+    report_status(client.update_corpus_coverage(Request::new(corpus_coverage)).await).await?;
+    Ok(())
+}
+
+// Corpus archive related utilities
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CorpusArchiveManifest {
+    label: String,
+    entries: Vec<CorpusArchiveEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CorpusArchiveEntry {
+    checksum: String,
+    worker_task_id: Option<i32>,
+    created_at: i64,
+    #[serde(default)]
+    size: i32,
+    #[serde(default)]
+    driver: Option<String>,
+    #[serde(default)]
+    coverage: Option<i32>,
+}
+
+impl From<&Corpus> for CorpusArchiveEntry {
+    fn from(corpus: &Corpus) -> Self {
+        Self {
+            checksum: corpus.checksum.clone(),
+            worker_task_id: corpus.worker_task_id,
+            created_at: corpus.created_at.seconds,
+            size: corpus.size,
+            driver: corpus.driver.clone(),
+            coverage: corpus.coverage,
+        }
+    }
+}
+
+fn append_to_archive<W: Write>(archive: &mut tar::Builder<W>, name: &str, content: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, name, content)
+}
+
+fn append_manifest_to_archive<W: Write>(
+    archive: &mut tar::Builder<W>,
+    manifest: &CorpusArchiveManifest,
+) -> io::Result<()> {
+    let manifest =
+        serde_yaml::to_vec(manifest).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?;
+    append_to_archive(archive, CORPUS_ARCHIVE_MANIFEST, &manifest)
+}
+
+fn append_corpus_to_archive<W: Write>(archive: &mut tar::Builder<W>, corpus: &Corpus) -> io::Result<()> {
+    let name = format!("{}.{}", corpus.checksum, CORPUS_FILE_EXT);
+    append_to_archive(archive, &name, &corpus.content)
+}
+
+/// Reads next entry of archive fully, along with its name
+fn next_archive_entry<R: Read>(entries: &mut tar::Entries<R>) -> io::Result<Option<(String, Vec<u8>)>> {
+    match entries.next() {
+        Some(entry) => {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            Ok(Some((name, content)))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn get_corpus_page(
+    label: String,
+    after_id: i32,
+    without_content: bool,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<Vec<Corpus>, Box<dyn Error>> {
+    let filter_corpus = xpc::FilterCorpus {
+        label,
+        created_after: prost_types::Timestamp::from(SystemTime::UNIX_EPOCH),
+        latest: Some(CORPUS_ARCHIVE_PAGE_SIZE),
+        without_content: Some(without_content),
+        after_id: Some(after_id),
+        ..Default::default()
+    };
+    download_filtered_corpus(filter_corpus, client).await
+}
+
+/// Export all corpus of a label along with a manifest to a zstd compressed tarball. Label is paged
+/// through, so that neither master nor we ever hold content of whole label
+pub async fn export_corpus_to_archive(
+    label: String,
+    path: &Path,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<usize, Box<dyn Error>> {
+    // Manifest goes first so that importers know what to expect before corpus, so metadata is
+    // collected first. Ids start from 1
+    let mut manifest = CorpusArchiveManifest {
+        label: label.clone(),
+        entries: Vec::new(),
+    };
+    let mut last_id = 0;
+    loop {
+        let page = get_corpus_page(label.clone(), last_id, true, client).await?;
+        match page.last() {
+            Some(corpus) => last_id = corpus.id,
+            None => break,
+        }
+        manifest.entries.extend(page.iter().map(CorpusArchiveEntry::from));
+    }
+
+    let file = File::create(path)?;
+    let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    task::block_in_place(|| append_manifest_to_archive(&mut archive, &manifest))?;
+
+    // Content is written as each page arrives, anything added after manifest was built is left out
+    let mut num_corpora = 0;
+    let mut after_id = 0;
+    while after_id < last_id {
+        let page = get_corpus_page(label.clone(), after_id, false, client).await?;
+        after_id = match page.last() {
+            Some(corpus) => corpus.id,
+            None => break,
+        };
+        task::block_in_place(|| -> io::Result<()> {
+            for corpus in page.iter().filter(|c| c.id <= last_id) {
+                append_corpus_to_archive(&mut archive, corpus)?;
+                num_corpora += 1;
+            }
+            Ok(())
+        })?;
+    }
+    task::block_in_place(|| -> io::Result<()> {
+        archive.into_inner()?.finish()?;
+        Ok(())
+    })?;
+
+    if num_corpora != manifest.entries.len() {
+        warn!(
+            "Exported {} corpus while manifest lists {}, rest were deleted meanwhile",
+            num_corpora,
+            manifest.entries.len()
+        );
+    }
+    debug!("Exported {} corpus to archive", num_corpora);
+    Ok(num_corpora)
+}
+
+/// Import corpus from an archive created by export, one entry at a time so that
+/// whole archive is never in memory. Entries whose content doesn't match checksum are skipped.
+pub async fn import_corpus_from_archive(
+    label: String,
+    path: &Path,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<usize, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = archive.entries()?;
+
+    let mut manifest: Option<CorpusArchiveManifest> = None;
+    let mut metadata: HashMap<String, (Option<i32>, Option<String>)> = HashMap::new();
+    let mut num_imported = 0;
+    loop {
+        // Decompression is blocking, so read each entry fully before going back to async land
+        let entry = task::block_in_place(|| next_archive_entry(&mut entries))?;
+
+        let (name, content) = match entry {
+            Some(e) => e,
+            None => break,
+        };
+
+        if name == CORPUS_ARCHIVE_MANIFEST {
+            let m: CorpusArchiveManifest = serde_yaml::from_slice(&content)?;
+            debug!("Importing {} corpus exported from label {}", m.entries.len(), m.label);
+            for e in m.entries.iter() {
+                metadata.insert(e.checksum.clone(), (e.worker_task_id, e.driver.clone()));
+            }
+            manifest = Some(m);
+            continue;
+        }
+
+        let expected_checksum = Path::new(&name).file_stem().map(|s| s.to_string_lossy().into_owned());
+        if Some(checksum(&content)) != expected_checksum {
+            warn!("Skipping {} from archive as checksum doesn't match", name);
+            continue;
+        }
+
+        let (worker_task_id, driver) = expected_checksum
+            .and_then(|c| metadata.get(&c).cloned())
+            .unwrap_or((None, None));
+        let mut result = upload_corpus(content.clone(), label.clone(), worker_task_id, driver.clone(), client).await;
+        // Originating worker task need not exist on this master, if archive came from another one
+        if result.is_err() && worker_task_id.is_some() {
+            debug!("Importing {} again without worker task {:?}", name, worker_task_id);
+            result = upload_corpus(content, label.clone(), None, driver, client).await;
+        }
+        if let Err(e) = result {
+            warn!("Failed to import {}: {}", name, e);
+            continue;
+        }
+        num_imported += 1;
+    }
+
+    match manifest {
+        Some(m) if m.entries.len() != num_imported => warn!(
+            "Imported {} corpus while manifest lists {}, rest failed or already exist",
+            num_imported,
+            m.entries.len()
+        ),
+        None => warn!("No manifest found in archive {:?}", path),
+        _ => {}
+    }
+
+    Ok(num_imported)
+}
+
+// Corpus snapshot related utilities
+pub async fn snapshot_corpus(
+    label: String,
+    name: String,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<CorpusSnapshot, Box<dyn Error>> {
+    debug!("Creating snapshot {} of corpus with label {}", name, label);
+    let new_snapshot = NewCorpusSnapshot { name, label };
+    let response = client.submit_corpus_snapshot(Request::new(new_snapshot)).await?;
+    Ok(response.into_inner())
+}
+
+pub async fn get_corpus_snapshots(
+    label: Option<String>,
+    name: Option<String>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<Vec<CorpusSnapshot>, Box<dyn Error>> {
+    let filter_snapshot = xpc::FilterCorpusSnapshot { label, name };
+    let response = client.get_corpus_snapshots(Request::new(filter_snapshot)).await?;
+    Ok(response.into_inner().data)
+}
+
+pub async fn restore_corpus_snapshot(
+    id: i32,
+    merge: bool,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<(), Box<dyn Error>> {
+    warn!("Restoring corpus snapshot {} (merge: {})", id, merge);
+    let restore_snapshot = xpc::RestoreCorpusSnapshot { id, merge };
+    client.restore_corpus_snapshot(Request::new(restore_snapshot)).await?;
+    Ok(())
+}
+
+pub async fn diff_corpus_snapshot(
+    id: i32,
+    against_id: Option<i32>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<xpc::CorpusSnapshotDiff, Box<dyn Error>> {
+    let diff_snapshot = xpc::DiffCorpusSnapshot { id, against_id };
+    let response = client.diff_corpus_snapshot(Request::new(diff_snapshot)).await?;
+    Ok(response.into_inner())
+}
+
+pub async fn delete_corpus_snapshot(id: i32, client: &mut OrchestratorClient<Channel>) -> Result<(), Box<dyn Error>> {
+    warn!("Deleting corpus snapshot {}", id);
+    let id = xpc::Id { value: id };
+    client.delete_corpus_snapshot(Request::new(id)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_archive_round_trip() {
+        let corpora: Vec<Corpus> = vec![b"first".to_vec(), b"second".to_vec()]
+            .into_iter()
+            .enumerate()
+            .map(|(i, content)| Corpus {
+                id: i as i32 + 1,
+                checksum: checksum(&content),
+                size: content.len() as i32,
+                content,
+                label: "test".to_owned(),
+                worker_task_id: Some(7),
+                created_at: prost_types::Timestamp::from(SystemTime::UNIX_EPOCH),
+                driver: Some("libfuzzer".to_owned()),
+                coverage: None,
+            })
+            .collect();
+        let manifest = CorpusArchiveManifest {
+            label: "test".to_owned(),
+            entries: corpora.iter().map(CorpusArchiveEntry::from).collect(),
+        };
+
+        let path = env::temp_dir().join(format!("{}.tar.zst", Uuid::new_v4()));
+        let mut archive = tar::Builder::new(zstd::Encoder::new(File::create(&path).unwrap(), 0).unwrap());
+        append_manifest_to_archive(&mut archive, &manifest).unwrap();
+        for corpus in corpora.iter() {
+            append_corpus_to_archive(&mut archive, corpus).unwrap();
+        }
+        archive.into_inner().unwrap().finish().unwrap();
+
+        let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(&path).unwrap()).unwrap());
+        let mut entries = archive.entries().unwrap();
+
+        // Manifest comes first & keeps metadata
+        let (name, content) = next_archive_entry(&mut entries).unwrap().unwrap();
+        assert_eq!(name, CORPUS_ARCHIVE_MANIFEST);
+        let read_manifest: CorpusArchiveManifest = serde_yaml::from_slice(&content).unwrap();
+        assert_eq!(read_manifest.entries.len(), 2);
+        assert_eq!(read_manifest.entries[0].worker_task_id, Some(7));
+        assert_eq!(read_manifest.entries[0].driver, Some("libfuzzer".to_owned()));
+
+        for corpus in corpora.iter() {
+            let (name, content) = next_archive_entry(&mut entries).unwrap().unwrap();
+            assert_eq!(name, format!("{}.{}", corpus.checksum, CORPUS_FILE_EXT));
+            assert_eq!(content, corpus.content);
+        }
+        assert!(next_archive_entry(&mut entries).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_cache() {
+        let root = env::temp_dir().join(Uuid::new_v4().to_string());
+        let cache = root.join("cache");
+        let dir = root.join("corpus");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let corpus = |content: &[u8], checksum: String| Corpus {
+            id: 1,
+            content: content.to_vec(),
+            checksum,
+            label: "test".to_owned(),
+            worker_task_id: None,
+            created_at: prost_types::Timestamp::from(SystemTime::UNIX_EPOCH),
+            size: content.len() as i32,
+            driver: None,
+            coverage: None,
+        };
+        let valid = corpus(b"valid", checksum(&b"valid".to_vec()));
+        let corrupt = corpus(b"corrupt", checksum(&b"other".to_vec()));
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            write_corpus_to_cache(&valid, &cache).await.unwrap();
+            write_corpus_to_cache(&corrupt, &cache).await.unwrap();
+            let checksums = vec![valid.checksum.as_str(), corrupt.checksum.as_str()];
+            link_cached_corpus(&cache, &dir, &checksums).await.unwrap();
+        });
+
+        // Only entries matching their checksum are cached, & linked as read-only
+        let cached_path = get_corpus_file_path(&cache, &valid.checksum);
+        let file_path = get_corpus_file_path(&dir, &valid.checksum);
+        assert_eq!(std::fs::read(&file_path).unwrap(), valid.content);
+        assert!(std::fs::metadata(&cached_path).unwrap().permissions().readonly());
+        assert_eq!(get_corpus_file_path(&dir, &corrupt.checksum).exists(), false);
+
+        // Entries are pruned once no task links them
+        assert_eq!(rt.block_on(prune_corpus_cache(&cache)).unwrap(), 0);
+        std::fs::remove_file(&file_path).unwrap();
+        assert_eq!(rt.block_on(prune_corpus_cache(&cache)).unwrap(), 1);
+        assert_eq!(cached_path.exists(), false);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
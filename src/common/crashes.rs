@@ -6,12 +6,15 @@ use tracing::debug;
 use tokio::fs;
 use tonic::{transport::channel::Channel, Request};
 
-use crate::models::{Crash, NewCrash, PatchCrash};
+use crate::common::outbox::submit_or_buffer;
+use crate::common::xpc::report_status;
+use crate::executor::crash_validator::VariantValidation;
+use crate::models::{Crash, CrashVariant, NewCrash, NewCrashVariant, PatchCrash};
 use crate::utils::{
     checksum,
     fs::{mkdir_p, read_file},
 };
-use crate::xpc::{self, orchestrator_client::OrchestratorClient};
+use crate::xpc::{self, orchestrator_client::OrchestratorClient, OutboxEntry};
 
 // Corpus related utilities
 pub async fn upload_crash_from_disk(
@@ -21,7 +24,8 @@ pub async fn upload_crash_from_disk(
     output: Option<String>,
     worker_task_id: Option<i32>,
     duplicate: Option<i32>,
-    client: &mut OrchestratorClient<Channel>,
+    validations: Vec<VariantValidation>,
+    client: Option<&mut OrchestratorClient<Channel>>,
 ) -> Result<(), Box<dyn Error>> {
     debug!("Trying to upload {:?} to crashes", file_path);
     let content = read_file(file_path).await?;
@@ -40,10 +44,45 @@ pub async fn upload_crash_from_disk(
         duplicate,
     };
 
-    client.submit_crash(Request::new(new_crash)).await?;
+    let new_variants = validations.into_iter().map(|v| new_crash_variant(0, v)).collect();
+
+    // Crashes are never reproduced again, so they are buffered on disk if master is down
+    submit_or_buffer(OutboxEntry::crash(new_crash, new_variants), client).await
+}
+
+fn new_crash_variant(crash_id: i32, validation: VariantValidation) -> NewCrashVariant {
+    NewCrashVariant {
+        crash_id,
+        variant: validation.variant,
+        verified: validation.verified,
+        output: Some(validation.output),
+    }
+}
+
+pub async fn upload_crash_variants(
+    crash_id: i32,
+    validations: Vec<VariantValidation>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<(), Box<dyn Error>> {
+    for validation in validations.into_iter() {
+        debug!("Uploading {} variant of crash {}", validation.variant, crash_id);
+        client
+            .submit_crash_variant(Request::new(new_crash_variant(crash_id, validation)))
+            .await?;
+    }
     Ok(())
 }
 
+pub async fn download_crash_variants(
+    crash_id: i32,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<Vec<CrashVariant>, Box<dyn Error>> {
+    let response = client
+        .get_crash_variants(Request::new(xpc::Id { value: crash_id }))
+        .await?;
+    Ok(response.into_inner().data)
+}
+
 pub async fn update_crash(
     id: i32,
     verified: bool,
@@ -82,7 +121,7 @@ pub async fn download_crashes(
         created_after: prost_types::Timestamp::from(created_after),
         duplicate,
     };
-    let response = client.get_crashes(Request::new(filter_request)).await?;
+    let response = report_status(client.get_crashes(Request::new(filter_request)).await).await?;
     Ok(response.into_inner().data)
 }
 
@@ -130,6 +169,19 @@ pub async fn download_crashes_to_disk(
             crash_output.set_extension("output");
             fs::write(crash_output, output).await?;
         }
+
+        // Variant results go next to crash as <checksum>.<variant>.output
+        for variant in download_crash_variants(crash.id, client).await?.iter() {
+            let variant_prefix = format!("{}.{}", crash.checksum, variant.variant);
+
+            if variant.verified {
+                fs::write(dir.join(format!("{}.verified", variant_prefix)), "").await?;
+            }
+
+            if let Some(output) = &variant.output {
+                fs::write(dir.join(format!("{}.output", variant_prefix)), output).await?;
+            }
+        }
     }
 
     Ok(crashes.len())
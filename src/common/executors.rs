@@ -2,10 +2,21 @@ use std::env;
 use std::error::Error;
 use std::io::{self, ErrorKind};
 use std::path::PathBuf;
+use std::time::Duration;
 
-use tracing::{debug, error};
+use heim::units::information;
+use tracing::{debug, error, warn};
 
-use crate::common::constants::WORKER_CONTAINER_VOLUME_MAP;
+use crate::common::constants::{
+    WORKER_CGROUP_ROOT, WORKER_CONTAINER_VOLUME_MAP, WORKER_MIN_DISK_FREE, WORKER_RESERVED_CPUS,
+    WORKER_RESERVED_MEMORY, WORKER_SHUTDOWN_TIMEOUT, WORKER_UUID,
+};
+
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup/fuzzy";
+pub const DEFAULT_MIN_DISK_FREE: &str = "1024";
+pub const DEFAULT_RESERVED_CPUS: &str = "0";
+pub const DEFAULT_RESERVED_MEMORY: &str = "0";
+pub const DEFAULT_SHUTDOWN_TIMEOUT: &str = "60";
 
 // Client pem utils
 pub fn set_container_volume_map(volume_map: &str) {
@@ -36,3 +47,123 @@ pub fn get_container_volume_map() -> Result<String, Box<dyn Error>> {
     let volume_map = volume_map?.to_owned();
     Ok(volume_map)
 }
+
+// Cgroup v2 subtree delegated to us, executor cgroups are created under this
+pub fn set_cgroup_root(cgroup_root: &str) {
+    debug!("Setting cgroup root to {}", cgroup_root);
+    env::set_var(WORKER_CGROUP_ROOT, cgroup_root);
+}
+
+pub fn get_cgroup_root() -> PathBuf {
+    PathBuf::from(env::var(WORKER_CGROUP_ROOT).unwrap_or_else(|_| DEFAULT_CGROUP_ROOT.to_owned()))
+}
+
+// Free space in MB on volume map below which corpus downloads & new tasks are paused
+pub fn set_min_disk_free(min_disk_free: &str) {
+    debug!("Setting minimum free disk to {} MB", min_disk_free);
+    env::set_var(WORKER_MIN_DISK_FREE, min_disk_free);
+}
+
+pub fn get_min_disk_free() -> i32 {
+    env::var(WORKER_MIN_DISK_FREE)
+        .ok()
+        .and_then(|min_disk_free| min_disk_free.parse::<i32>().ok())
+        .unwrap_or_else(|| DEFAULT_MIN_DISK_FREE.parse::<i32>().unwrap())
+}
+
+/// Total & free space in MB on volume map, where all task directories are created
+pub async fn get_volume_disk_usage() -> Result<(i32, i32), Box<dyn Error>> {
+    let (_, mapped_path) = extract_contraint_volume_map(get_container_volume_map()?.as_str());
+    let usage = heim::disk::usage(&mapped_path).await?;
+    Ok((
+        usage.total().get::<information::megabyte>() as i32,
+        usage.free().get::<information::megabyte>() as i32,
+    ))
+}
+
+/// Unknown usage is not treated as low, so that a failing probe doesn't stall the worker
+pub async fn is_disk_low() -> bool {
+    match get_volume_disk_usage().await {
+        Ok((_, free)) => free < get_min_disk_free(),
+        Err(e) => {
+            warn!("Unable to get disk usage of volume map: {}", e);
+            false
+        }
+    }
+}
+
+// Cpus & memory (in MB) kept for host, syncers & validators, these are not advertised to master
+pub fn set_reserved_capacity(reserved_cpus: &str, reserved_memory: &str) {
+    debug!(
+        "Reserving {} cpus & {} MB memory for host",
+        reserved_cpus, reserved_memory
+    );
+    env::set_var(WORKER_RESERVED_CPUS, reserved_cpus);
+    env::set_var(WORKER_RESERVED_MEMORY, reserved_memory);
+}
+
+pub fn validate_reserved_capacity(reserved_cpus: &str, reserved_memory: &str) -> Result<(), Box<dyn Error>> {
+    for (resource, reserved) in [("cpus", reserved_cpus), ("memory", reserved_memory)].iter() {
+        match reserved.parse::<i32>() {
+            Ok(reserved) if reserved >= 0 => {}
+            _ => {
+                let err = format!("Reserved {} {:?} is not a non negative number", resource, reserved);
+                return Err(Box::new(io::Error::new(ErrorKind::InvalidInput, err.as_str())));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn get_reserved_cpus() -> i32 {
+    env::var(WORKER_RESERVED_CPUS)
+        .ok()
+        .and_then(|reserved_cpus| reserved_cpus.parse::<i32>().ok())
+        .unwrap_or(0)
+}
+
+pub fn get_reserved_memory() -> i32 {
+    env::var(WORKER_RESERVED_MEMORY)
+        .ok()
+        .and_then(|reserved_memory| reserved_memory.parse::<i32>().ok())
+        .unwrap_or(0)
+}
+
+// Seconds worker waits for tasks to stop on shutdown, whatever stops worker has to wait longer than this
+pub fn set_shutdown_timeout(shutdown_timeout: &str) {
+    debug!("Setting shutdown timeout to {} seconds", shutdown_timeout);
+    env::set_var(WORKER_SHUTDOWN_TIMEOUT, shutdown_timeout);
+}
+
+pub fn get_shutdown_timeout() -> Duration {
+    let seconds = env::var(WORKER_SHUTDOWN_TIMEOUT)
+        .ok()
+        .and_then(|shutdown_timeout| shutdown_timeout.parse::<u64>().ok())
+        .unwrap_or_else(|| DEFAULT_SHUTDOWN_TIMEOUT.parse::<u64>().unwrap());
+    Duration::from_secs(seconds)
+}
+
+// Containers are labelled with worker uuid, so that a restarted worker can find ones it left behind
+pub fn set_worker_uuid(uuid: &str) {
+    debug!("Setting worker uuid to {}", uuid);
+    env::set_var(WORKER_UUID, uuid);
+}
+
+/// Not present when executors are used outside of a worker, like from cli
+pub fn get_worker_uuid() -> Option<String> {
+    env::var(WORKER_UUID).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_validate_reserved_capacity() {
+        assert!(validate_reserved_capacity("0", "0").is_ok());
+        assert!(validate_reserved_capacity("2", "4096").is_ok());
+        assert!(validate_reserved_capacity("-1", "0").is_err());
+        assert!(validate_reserved_capacity("0", "-4096").is_err());
+        assert!(validate_reserved_capacity("two", "0").is_err());
+        assert!(validate_reserved_capacity("0", "4G").is_err());
+    }
+}
@@ -7,6 +7,17 @@ pub const WORKER_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(COMMON);
 pub const WORKER_FUZZDRIVER_STAT_UPLOAD_INTERVAL: Duration = Duration::from_secs(COMMON / 2);
 pub const WORKER_TASK_REFRESH_INTERVAL: Duration = Duration::from_secs(COMMON);
 pub const WORKER_PROCESS_CHECK_INTERVAL: Duration = Duration::from_secs(COMMON / 2);
+pub const WORKER_CORPUS_UPLOAD_DEBOUNCE: Duration = Duration::from_secs(2);
+pub const WORKER_LOGS_UPLOAD_INTERVAL: Duration = Duration::from_secs(5);
+pub const WORKER_OUTBOX_DRAIN_INTERVAL: Duration = Duration::from_secs(COMMON / 6);
+pub const WORKER_OUTBOX_MAX_BACKOFF: Duration = Duration::from_secs(COMMON * 5);
+pub const WORKER_CONNECT_BACKOFF: Duration = Duration::from_secs(1);
+pub const WORKER_CONNECT_MAX_BACKOFF: Duration = Duration::from_secs(COMMON * 2);
+pub const WORKER_REQUEST_TIMEOUT: Duration = Duration::from_secs(COMMON * 5);
+pub const WORKER_PROBE_TIMEOUT: Duration = Duration::from_secs(COMMON / 6);
+
+// Cli related
+pub const CLI_LOGS_FOLLOW_INTERVAL: Duration = Duration::from_secs(2);
 
 // Master related
 pub const MASTER_SCHEDULER_INTERVAL: Duration = Duration::from_secs(COMMON);
@@ -1,9 +1,11 @@
+pub mod artifacts;
 pub mod cli;
 pub mod constants;
 pub mod corpora;
 pub mod crashes;
 pub mod executors;
 pub mod intervals;
+pub mod outbox;
 pub mod profiles;
 pub mod tasks;
 pub mod worker_tasks;
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use prost::Message;
+use tracing::{debug, info, warn};
+use tokio::fs;
+use tonic::{transport::channel::Channel, Code, Request, Status};
+use uuid::Uuid;
+
+use crate::common::constants::WORKER_OUTBOX_DIR;
+use crate::common::intervals::{WORKER_OUTBOX_DRAIN_INTERVAL, WORKER_OUTBOX_MAX_BACKOFF};
+use crate::common::xpc::{get_orchestrator_client, report_unavailable};
+use crate::models::{NewCorpus, NewCrash, NewCrashVariant, NewFuzzStat, NewTraceEvent};
+use crate::utils::fs::mkdir_p;
+use crate::xpc::{orchestrator_client::OrchestratorClient, outbox_entry::Entry, OutboxEntry};
+
+pub const DEFAULT_OUTBOX_DIR: &str = ".fuzzy_outbox";
+const OUTBOX_ENTRY_EXT: &str = "entry";
+// Entries master rejects or that keep timing out are moved here, rather than blocking the rest
+const OUTBOX_DEAD_LETTER_DIR: &str = "dead";
+// Times an entry may time out before giving up on it, master being down doesn't count
+const OUTBOX_ENTRY_MAX_ATTEMPTS: u32 = 10;
+// Further uploads are dropped once buffered entries, dead ones included, take up this many bytes
+const OUTBOX_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+// Oldest dead entries are removed beyond this many bytes, they are only kept for inspection
+const OUTBOX_DEAD_LETTER_MAX_SIZE: u64 = OUTBOX_MAX_SIZE / 10;
+
+lazy_static! {
+    // Bytes taken by each outbox, counted once & kept up to date after. Counting on every push gets
+    // slower as entries pile up, which is exactly while master is down
+    static ref OUTBOX_SIZES: Mutex<HashMap<PathBuf, u64>> = Mutex::new(HashMap::new());
+}
+
+// Uploads that fail due to master being unreachable are kept here till they can be drained
+pub fn set_outbox_dir(outbox_dir: &str) {
+    debug!("Setting outbox dir to {}", outbox_dir);
+    env::set_var(WORKER_OUTBOX_DIR, outbox_dir);
+}
+
+pub fn get_outbox_dir() -> PathBuf {
+    PathBuf::from(env::var(WORKER_OUTBOX_DIR).unwrap_or_else(|_| DEFAULT_OUTBOX_DIR.to_owned()))
+}
+
+impl OutboxEntry {
+    pub fn crash(new_crash: NewCrash, crash_variants: Vec<NewCrashVariant>) -> Self {
+        Self {
+            entry: Some(Entry::Crash(new_crash)),
+            crash_variants,
+        }
+    }
+
+    pub fn corpus(new_corpus: NewCorpus) -> Self {
+        Self {
+            entry: Some(Entry::Corpus(new_corpus)),
+            crash_variants: Vec::new(),
+        }
+    }
+
+    pub fn fuzz_stat(new_fuzz_stat: NewFuzzStat) -> Self {
+        Self {
+            entry: Some(Entry::FuzzStat(new_fuzz_stat)),
+            crash_variants: Vec::new(),
+        }
+    }
+
+    pub fn trace_event(new_trace_event: NewTraceEvent) -> Self {
+        Self {
+            entry: Some(Entry::TraceEvent(new_trace_event)),
+            crash_variants: Vec::new(),
+        }
+    }
+}
+
+/// Rejections by master (constraint failures etc..) will never succeed, so only these are retried
+fn is_retriable(status: &Status) -> bool {
+    match status.code() {
+        Code::Unavailable | Code::DeadlineExceeded => true,
+        _ => false,
+    }
+}
+
+async fn submit_entry(entry: &OutboxEntry, client: &mut OrchestratorClient<Channel>) -> Result<(), Status> {
+    match entry.entry.clone() {
+        Some(Entry::Crash(new_crash)) => {
+            let crash_id = client.submit_crash(Request::new(new_crash)).await?.into_inner().value;
+            // Crash is already in, so a failed variant is not worth resubmitting crash for
+            for mut new_variant in entry.crash_variants.clone() {
+                new_variant.crash_id = crash_id;
+                if let Err(e) = client.submit_crash_variant(Request::new(new_variant)).await {
+                    warn!("Failed to submit variant of crash {}: {}", crash_id, e);
+                }
+            }
+        }
+        Some(Entry::Corpus(new_corpus)) => {
+            client.submit_corpus(Request::new(new_corpus)).await?;
+        }
+        Some(Entry::FuzzStat(new_fuzz_stat)) => {
+            client.submit_fuzz_stat(Request::new(new_fuzz_stat)).await?;
+        }
+        Some(Entry::TraceEvent(new_trace_event)) => {
+            client.submit_trace_event(Request::new(new_trace_event)).await?;
+        }
+        None => warn!("Empty outbox entry, nothing to submit"),
+    }
+    Ok(())
+}
+
+/// Entries are named by creation time, so that draining happens in same order. Failed attempts
+/// are kept in name too, like <created_at>-<uuid>.<attempts>.entry
+async fn push_to_outbox(outbox_dir: &Path, entry: &OutboxEntry) -> Result<(), Box<dyn Error>> {
+    mkdir_p(outbox_dir).await?;
+
+    let mut content = Vec::with_capacity(entry.encoded_len());
+    entry.encode(&mut content)?;
+
+    reserve_outbox_space(outbox_dir, content.len() as u64).await?;
+
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let entry_path = outbox_dir.join(format!("{:020}-{}.0.{}", created_at, Uuid::new_v4(), OUTBOX_ENTRY_EXT));
+
+    // Write & rename, so that a crash midway never leaves a truncated entry behind
+    let partial_path = entry_path.with_extension("partial");
+    fs::write(&partial_path, content).await?;
+    fs::rename(&partial_path, &entry_path).await?;
+    Ok(())
+}
+
+async fn get_entries_size(entries: &[PathBuf]) -> Result<u64, Box<dyn Error>> {
+    let mut size = 0;
+    for entry_path in entries {
+        size += fs::metadata(entry_path).await?.len();
+    }
+    Ok(size)
+}
+
+async fn reserve_outbox_space(outbox_dir: &Path, len: u64) -> Result<(), Box<dyn Error>> {
+    let counted = OUTBOX_SIZES.lock().unwrap().contains_key(outbox_dir);
+    let mut scanned = 0;
+    if counted == false {
+        scanned += get_entries_size(&get_outbox_entries(outbox_dir).await?).await?;
+        let dead_letter_dir = outbox_dir.join(OUTBOX_DEAD_LETTER_DIR);
+        scanned += get_entries_size(&get_outbox_entries(&dead_letter_dir).await?).await?;
+    }
+
+    let mut sizes = OUTBOX_SIZES.lock().unwrap();
+    let size = sizes.entry(outbox_dir.to_path_buf()).or_insert(scanned);
+    if *size + len > OUTBOX_MAX_SIZE {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Outbox at {:?} is full, dropping upload", outbox_dir),
+        )));
+    }
+    *size += len;
+    Ok(())
+}
+
+fn release_outbox_space(outbox_dir: &Path, len: u64) {
+    if let Some(size) = OUTBOX_SIZES.lock().unwrap().get_mut(outbox_dir) {
+        *size = size.saturating_sub(len);
+    }
+}
+
+/// Submits entry to master, buffering it into outbox if master is unreachable or there isn't even
+/// a client to try with
+pub async fn submit_or_buffer(
+    entry: OutboxEntry,
+    client: Option<&mut OrchestratorClient<Channel>>,
+) -> Result<(), Box<dyn Error>> {
+    let status = match client {
+        Some(client) => match submit_entry(&entry, client).await {
+            Ok(()) => return Ok(()),
+            Err(status) => {
+                report_unavailable(&status).await;
+                status
+            }
+        },
+        None => Status::unavailable("Not connected to master"),
+    };
+    if is_retriable(&status) {
+        // Not a warning, as those are shipped to master as trace events & would get buffered as well
+        info!("Master unreachable, buffering upload into outbox: {}", status.message());
+        push_to_outbox(&get_outbox_dir(), &entry).await
+    } else {
+        Err(Box::new(status))
+    }
+}
+
+async fn get_outbox_entries(outbox_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    if outbox_dir.is_dir() == false {
+        return Ok(entries);
+    }
+
+    let mut dir = fs::read_dir(outbox_dir).await?;
+    while let Some(dir_entry) = dir.next_entry().await? {
+        let path = dir_entry.path();
+        if path.extension().map(|e| e == OUTBOX_ENTRY_EXT).unwrap_or(false) {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+fn get_entry_attempts(entry_path: &Path) -> u32 {
+    entry_path
+        .file_stem()
+        .and_then(|stem| Path::new(stem).extension())
+        .and_then(|attempts| attempts.to_str())
+        .and_then(|attempts| attempts.parse().ok())
+        .unwrap_or(0)
+}
+
+fn get_entry_path_with_attempts(entry_path: &Path, attempts: u32) -> PathBuf {
+    // Strips both extensions, even from entries named before attempts were kept
+    let stem = entry_path.file_stem().map(Path::new).and_then(|stem| stem.file_stem());
+    let stem = stem.map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    entry_path.with_file_name(format!("{}.{}.{}", stem, attempts, OUTBOX_ENTRY_EXT))
+}
+
+/// Dead entries still count towards outbox size, so oldest ones are removed beyond their own limit
+async fn move_to_dead_letter(outbox_dir: &Path, entry_path: &Path) -> Result<(), Box<dyn Error>> {
+    let dead_letter_dir = outbox_dir.join(OUTBOX_DEAD_LETTER_DIR);
+    mkdir_p(&dead_letter_dir).await?;
+    if let Some(name) = entry_path.file_name() {
+        fs::rename(entry_path, dead_letter_dir.join(name)).await?;
+    }
+
+    let mut dead_entries = Vec::new();
+    let mut size = 0;
+    for dead_path in get_outbox_entries(&dead_letter_dir).await? {
+        let len = fs::metadata(&dead_path).await?.len();
+        size += len;
+        dead_entries.push((dead_path, len));
+    }
+    // Entries are sorted by creation time
+    for (dead_path, len) in dead_entries {
+        if size <= OUTBOX_DEAD_LETTER_MAX_SIZE {
+            break;
+        }
+        debug!(
+            "Removing dead outbox entry {:?} as dead entries are over limit",
+            dead_path
+        );
+        fs::remove_file(&dead_path).await?;
+        release_outbox_space(outbox_dir, len);
+        size -= len;
+    }
+    Ok(())
+}
+
+/// Submits buffered entries in order, stops at first one that still can't reach master
+pub async fn drain_outbox(client: &mut OrchestratorClient<Channel>) -> Result<usize, Box<dyn Error>> {
+    drain_outbox_entries(&get_outbox_dir(), |entry| {
+        let mut client = client.clone();
+        async move {
+            let result = submit_entry(&entry, &mut client).await;
+            if let Err(status) = result.as_ref() {
+                report_unavailable(status).await;
+            }
+            result
+        }
+    })
+    .await
+}
+
+async fn drain_outbox_entries<F, Fut>(outbox_dir: &Path, mut submit: F) -> Result<usize, Box<dyn Error>>
+where
+    F: FnMut(OutboxEntry) -> Fut,
+    Fut: Future<Output = Result<(), Status>>,
+{
+    let mut drained = 0;
+    for entry_path in get_outbox_entries(outbox_dir).await? {
+        let content = fs::read(&entry_path).await?;
+        let entry = match OutboxEntry::decode(&content[..]) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Unable to decode outbox entry {:?}, giving up on it: {}", entry_path, e);
+                move_to_dead_letter(outbox_dir, &entry_path).await?;
+                continue;
+            }
+        };
+
+        match submit(entry).await {
+            Ok(()) => {
+                drained += 1;
+                fs::remove_file(&entry_path).await?;
+                release_outbox_space(outbox_dir, content.len() as u64);
+            }
+            Err(status) if status.code() == Code::Unavailable => return Err(Box::new(status)),
+            Err(status) if is_retriable(&status) => {
+                let attempts = get_entry_attempts(&entry_path) + 1;
+                if attempts < OUTBOX_ENTRY_MAX_ATTEMPTS {
+                    fs::rename(&entry_path, get_entry_path_with_attempts(&entry_path, attempts)).await?;
+                    return Err(Box::new(status));
+                }
+                warn!(
+                    "Outbox entry {:?} failed {} times, giving up on it: {}",
+                    entry_path, attempts, status
+                );
+                move_to_dead_letter(outbox_dir, &entry_path).await?;
+            }
+            Err(status) => {
+                warn!(
+                    "Master rejected outbox entry {:?}, giving up on it: {}",
+                    entry_path, status
+                );
+                move_to_dead_letter(outbox_dir, &entry_path).await?;
+            }
+        }
+    }
+    Ok(drained)
+}
+
+/// Keeps draining outbox for lifetime of worker, backing off while master stays unreachable
+pub async fn drain_outbox_forever() {
+    let mut backoff = WORKER_OUTBOX_DRAIN_INTERVAL;
+    loop {
+        tokio::time::delay_for(backoff).await;
+
+        let result = match get_orchestrator_client().await.map_err(|e| e.to_string()) {
+            Ok(mut client) => drain_outbox(&mut client).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(drained) => {
+                if drained > 0 {
+                    info!("Drained {} buffered uploads from outbox", drained);
+                }
+                backoff = WORKER_OUTBOX_DRAIN_INTERVAL;
+            }
+            Err(e) => {
+                backoff = std::cmp::min(backoff * 2, WORKER_OUTBOX_MAX_BACKOFF);
+                info!("Unable to drain outbox, will retry in {:?}: {}", backoff, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace_event_entry(message: &str) -> OutboxEntry {
+        OutboxEntry::trace_event(NewTraceEvent {
+            worker_id: None,
+            level: 0,
+            target: "test".to_owned(),
+            message: message.to_owned(),
+        })
+    }
+
+    fn get_message(entry: OutboxEntry) -> String {
+        match entry.entry {
+            Some(Entry::TraceEvent(e)) => e.message,
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn test_drain_outbox_entries() {
+        let outbox_dir = env::temp_dir().join(format!("fuzzy_outbox_{}", Uuid::new_v4()));
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            // Written before first push, so that it is counted in outbox size
+            mkdir_p(&outbox_dir).await.unwrap();
+            fs::write(outbox_dir.join(format!("{:020}-garbage.0.entry", u64::MAX)), b"\xff")
+                .await
+                .unwrap();
+            for message in vec!["poison", "rejected", "good"] {
+                push_to_outbox(&outbox_dir, &trace_event_entry(message)).await.unwrap();
+            }
+
+            // Master being down neither drains nor costs an attempt
+            let drained = drain_outbox_entries(&outbox_dir, |_| async { Err(Status::unavailable("down")) }).await;
+            assert!(drained.is_err());
+            let entries = get_outbox_entries(&outbox_dir).await.unwrap();
+            assert_eq!(entries.len(), 4);
+            assert_eq!(get_entry_attempts(&entries[0]), 0);
+
+            let submit = |entry: OutboxEntry| async move {
+                match get_message(entry).as_str() {
+                    "poison" => Err(Status::deadline_exceeded("slow")),
+                    "rejected" => Err(Status::invalid_argument("bad")),
+                    _ => Ok(()),
+                }
+            };
+            // Poison entry holds up rest till it runs out of attempts
+            for attempts in 1..OUTBOX_ENTRY_MAX_ATTEMPTS {
+                assert!(drain_outbox_entries(&outbox_dir, submit).await.is_err());
+                let entries = get_outbox_entries(&outbox_dir).await.unwrap();
+                assert_eq!(get_entry_attempts(&entries[0]), attempts);
+            }
+            assert_eq!(drain_outbox_entries(&outbox_dir, submit).await.unwrap(), 1);
+            assert!(get_outbox_entries(&outbox_dir).await.unwrap().is_empty());
+
+            let dead_letter_dir = outbox_dir.join(OUTBOX_DEAD_LETTER_DIR);
+            let dead_entries = get_outbox_entries(&dead_letter_dir).await.unwrap();
+            assert_eq!(dead_entries.len(), 3);
+
+            // Drained entries are no longer counted, dead ones still are
+            let dead_size = get_entries_size(&dead_entries).await.unwrap();
+            assert_eq!(OUTBOX_SIZES.lock().unwrap().get(&outbox_dir), Some(&dead_size));
+        });
+        std::fs::remove_dir_all(&outbox_dir).unwrap();
+    }
+}
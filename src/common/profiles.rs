@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::error::Error;
 use std::path::Path;
 
@@ -7,8 +8,9 @@ use crate::fuzz_driver::FuzzConfig;
 use crate::utils::fs::read_file;
 
 pub fn construct_profile(yaml_string: &str) -> Result<FuzzConfig, Box<dyn Error>> {
-    let profile: FuzzConfig = serde_yaml::from_str(yaml_string)?;
+    let mut profile: FuzzConfig = serde_yaml::from_str(yaml_string)?;
     profile.validate()?;
+    profile.apply_variant();
     Ok(profile)
 }
 
@@ -27,6 +29,30 @@ pub fn validate_fuzz_profile(config: &FuzzConfig) -> Result<(), ValidationError>
             "Fuzz stat collectors are not supported when corpus upload is disabled.",
         ));
     }
+
+    if config.corpus.seed_labels.contains(&config.corpus.label) {
+        return Err(ValidationError::new("Corpus label cannot be one of the seed labels."));
+    }
+
+    // Variant outputs are stored against their name
+    let mut variant_names = HashSet::new();
+    if config
+        .crash
+        .variants
+        .iter()
+        .any(|v| variant_names.insert(&v.name) == false)
+    {
+        return Err(ValidationError::new("Build variant names have to be unique."));
+    }
+
+    if let Some(name) = config.variant.as_ref() {
+        let variant = config.crash.variants.iter().find(|v| &v.name == name);
+        if variant.map(|v| v.execution.is_some()) != Some(true) {
+            return Err(ValidationError::new(
+                "Fuzzed variant has to be one of build variants with an execution.",
+            ));
+        }
+    }
     Ok(())
 }
 
@@ -1,9 +1,10 @@
 use std::error::Error;
 
-use tonic::Request;
+use tonic::{transport::channel::Channel, Request};
 
-use crate::common::xpc::get_orchestrator_client;
-use crate::models::PatchWorkerTask;
+use crate::common::xpc::{get_orchestrator_client, report_status};
+use crate::models::{NewWorkerTaskLog, PatchWorkerTask, WorkerTaskLog};
+use crate::xpc::{orchestrator_client::OrchestratorClient, FilterWorkerTaskLog, NewWorkerTaskLogs};
 
 pub async fn mark_worker_task_active(worker_task_id: Option<i32>) -> Result<(), Box<dyn Error>> {
     if worker_task_id.is_some() {
@@ -13,9 +14,10 @@ pub async fn mark_worker_task_active(worker_task_id: Option<i32>) -> Result<(),
         let patch_worker_task = PatchWorkerTask {
             id: worker_task_id,
             running: true,
+            restarts: Some(0),
         };
 
-        client.update_worker_task(Request::new(patch_worker_task)).await?;
+        report_status(client.update_worker_task(Request::new(patch_worker_task)).await).await?;
     }
     Ok(())
 }
@@ -28,9 +30,52 @@ pub async fn mark_worker_task_inactive(worker_task_id: Option<i32>) -> Result<()
         let patch_worker_task = PatchWorkerTask {
             id: worker_task_id,
             running: false,
+            restarts: None,
         };
 
-        client.update_worker_task(Request::new(patch_worker_task)).await?;
+        report_status(client.update_worker_task(Request::new(patch_worker_task)).await).await?;
     }
     Ok(())
 }
+
+pub async fn update_worker_task_restarts(worker_task_id: Option<i32>, restarts: i32) -> Result<(), Box<dyn Error>> {
+    if worker_task_id.is_some() {
+        let worker_task_id = worker_task_id.unwrap();
+        let mut client = get_orchestrator_client().await?;
+
+        let patch_worker_task = PatchWorkerTask {
+            id: worker_task_id,
+            running: true,
+            restarts: Some(restarts),
+        };
+
+        report_status(client.update_worker_task(Request::new(patch_worker_task)).await).await?;
+    }
+    Ok(())
+}
+
+pub async fn upload_worker_task_logs(
+    logs: Vec<NewWorkerTaskLog>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<(), Box<dyn Error>> {
+    let result = client
+        .submit_worker_task_logs(Request::new(NewWorkerTaskLogs { data: logs }))
+        .await;
+    report_status(result).await?;
+    Ok(())
+}
+
+pub async fn download_worker_task_logs(
+    worker_task_id: i32,
+    after_id: Option<i32>,
+    limit: Option<i64>,
+    client: &mut OrchestratorClient<Channel>,
+) -> Result<Vec<WorkerTaskLog>, Box<dyn Error>> {
+    let filter_log = FilterWorkerTaskLog {
+        worker_task_id,
+        after_id,
+        limit,
+    };
+    let response = client.get_worker_task_logs(Request::new(filter_log)).await?;
+    Ok(response.into_inner().data)
+}
@@ -1,15 +1,36 @@
+use std::cmp::min;
 use std::env;
 use std::error::Error;
+use std::io;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use tracing::{debug, error};
+use lazy_static::lazy_static;
+use tracing::{debug, error, warn};
+use tokio::sync::Mutex;
 use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Code, Status};
+use uuid::Uuid;
 
 use crate::common::constants::{
     WORKER_CONNECT_ADDR_ENV_KEY, WORKER_CONNECT_CACERT_ENV_KEY, WORKER_CONNECT_WORKERPEM_ENV_KEY,
 };
+use crate::common::intervals::{WORKER_CONNECT_BACKOFF, WORKER_CONNECT_MAX_BACKOFF, WORKER_REQUEST_TIMEOUT};
 use crate::utils::fs::read_file;
-use crate::xpc::orchestrator_client::OrchestratorClient;
+use crate::xpc::{collector_client::CollectorClient, orchestrator_client::OrchestratorClient};
+
+lazy_static! {
+    // One channel is shared by everything in process, it connects lazily on first request. Lock is
+    // never held across network or file io
+    static ref SHARED_CHANNEL: Mutex<SharedChannel> = Mutex::new(SharedChannel::default());
+}
+
+#[derive(Default)]
+struct SharedChannel {
+    channel: Option<Channel>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
 
 // Client pem utils
 pub fn set_worker_pem(path: &str) {
@@ -68,14 +89,123 @@ pub async fn get_server_endpoint() -> Result<Endpoint, Box<dyn Error>> {
         .ca_certificate(Certificate::from_pem(ca_cert_bytes))
         .identity(Identity::from_pem(&worker_pem_bytes, &worker_pem_bytes));
 
-    let endpoint = tonic::transport::Channel::from_shared(url)?.tls_config(tls_config);
+    // Hung requests would otherwise hold up uploads & heartbeats forever
+    let endpoint = tonic::transport::Channel::from_shared(url)?
+        .tls_config(tls_config)
+        .timeout(WORKER_REQUEST_TIMEOUT);
 
     Ok(endpoint)
 }
 
+/// Exponential backoff with equal jitter, so that workers losing master together don't reconnect together
+fn get_connect_backoff(failures: u32) -> Duration {
+    let exponent = min(failures.saturating_sub(1), 16);
+    let backoff = min(WORKER_CONNECT_BACKOFF * 2u32.pow(exponent), WORKER_CONNECT_MAX_BACKOFF);
+
+    let mut random = [0u8; 8];
+    random.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+    let jitter = u64::from_le_bytes(random) % (backoff.as_millis() as u64 / 2 + 1);
+
+    backoff / 2 + Duration::from_millis(jitter)
+}
+
+async fn get_shared_channel() -> Result<Channel, Box<dyn Error>> {
+    {
+        let shared = SHARED_CHANNEL.lock().await;
+        if let Some(channel) = shared.channel.as_ref() {
+            return Ok(channel.clone());
+        }
+
+        if let Some(retry_at) = shared.retry_at {
+            let now = Instant::now();
+            if retry_at > now {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    format!("Master unreachable, next connection attempt in {:?}", retry_at - now),
+                )));
+            }
+        }
+    }
+
+    // Creating channel doesn't touch network, connection happens with first request & failures
+    // of those are reported back to back off
+    let channel = get_server_endpoint().await?.connect_lazy()?;
+
+    let mut shared = SHARED_CHANNEL.lock().await;
+    // Someone else might have been quicker
+    if let Some(channel) = shared.channel.as_ref() {
+        return Ok(channel.clone());
+    }
+    debug!("Created channel to master after {} failures", shared.failures);
+    shared.retry_at = None;
+    shared.channel = Some(channel.clone());
+    Ok(channel)
+}
+
+/// Requests that couldn't reach master drop shared channel, so that reconnection happens only after a
+/// backoff with jitter instead of every caller hammering master as soon as it is back
+pub async fn report_unavailable(status: &Status) {
+    if status.code() != Code::Unavailable {
+        return;
+    }
+
+    let mut shared = SHARED_CHANNEL.lock().await;
+    // Other requests over same channel might have failed too, only first one counts
+    if shared.channel.take().is_none() {
+        return;
+    }
+    shared.failures += 1;
+    let backoff = get_connect_backoff(shared.failures);
+    shared.retry_at = Some(Instant::now() + backoff);
+    warn!(
+        "Unable to reach master, backing off for {:?}: {}",
+        backoff,
+        status.message()
+    );
+}
+
+/// Passes result of a request along, reporting it first in case master couldn't be reached
+pub async fn report_status<T>(result: Result<T, Status>) -> Result<T, Status> {
+    if let Err(status) = result.as_ref() {
+        report_unavailable(status).await;
+    }
+    result
+}
+
+/// Reachability of master is known only through requests, so failures are reset once one succeeds
+pub async fn report_available() {
+    SHARED_CHANNEL.lock().await.failures = 0;
+}
+
+/// Next client connects afresh, picking up changed url or certificates
+pub async fn reset_shared_channel() {
+    debug!("Resetting shared channel to master");
+    *SHARED_CHANNEL.lock().await = SharedChannel::default();
+}
+
 pub async fn get_orchestrator_client() -> Result<OrchestratorClient<Channel>, Box<dyn Error>> {
-    let endpoint = get_server_endpoint().await?;
-    let channel = endpoint.connect().await?;
+    let channel = get_shared_channel().await?;
     let client = OrchestratorClient::new(channel);
     Ok(client)
 }
+
+pub async fn get_collector_client() -> Result<CollectorClient<Channel>, Box<dyn Error>> {
+    let channel = get_shared_channel().await?;
+    let client = CollectorClient::new(channel);
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_get_connect_backoff() {
+        for failures in 1..40 {
+            let backoff = get_connect_backoff(failures);
+            assert!(backoff >= WORKER_CONNECT_BACKOFF / 2);
+            assert!(backoff <= WORKER_CONNECT_MAX_BACKOFF);
+        }
+        // Grows with failures till capped
+        assert!(get_connect_backoff(6) >= WORKER_CONNECT_BACKOFF * 16);
+    }
+}
@@ -0,0 +1,102 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tracing::{debug, warn};
+use tokio::fs;
+
+use super::ResourceLimits;
+use crate::common::executors::get_cgroup_root;
+
+// Period over which cpu quota is enforced, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+// Script to join a cgroup before exec'ing into actual command, so no child escapes the limits
+pub const CGROUP_JOIN_SCRIPT: &str = r#"echo $$ > "$1" && shift && exec "$@""#;
+
+/// A cgroup v2 under the root delegated to worker, one per spawned process tree
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub async fn create(name: &str, limits: &ResourceLimits) -> io::Result<Self> {
+        let root = get_cgroup_root();
+        fs::create_dir_all(&root).await?;
+
+        // Controllers have to be enabled on parent for limit files to show up in children
+        if let Err(e) = fs::write(root.join("cgroup.subtree_control"), "+memory +cpu +pids +io").await {
+            warn!("Unable to enable cgroup controllers at {:?}: {}", root, e);
+        }
+
+        let path = root.join(name);
+        debug!("Creating cgroup at {:?}", path);
+        fs::create_dir(&path).await?;
+        let cgroup = Self { path };
+
+        if let Some(memory_max) = limits.memory_max {
+            cgroup.write("memory.max", format!("{}", memory_max)).await?;
+        }
+        if let Some(cpu_quota) = limits.cpu_quota {
+            let quota = (cpu_quota * CPU_PERIOD as f64) as u64;
+            cgroup.write("cpu.max", format!("{} {}", quota, CPU_PERIOD)).await?;
+        }
+        if let Some(pids_max) = limits.pids_max {
+            cgroup.write("pids.max", format!("{}", pids_max)).await?;
+        }
+        if let Some(io_weight) = limits.io_weight {
+            cgroup.write("io.weight", format!("default {}", io_weight)).await?;
+        }
+        Ok(cgroup)
+    }
+
+    async fn write(&self, file: &str, value: String) -> io::Result<()> {
+        debug!("Setting {} to {} for cgroup {:?}", file, value, self.path);
+        fs::write(self.path.join(file), value).await
+    }
+
+    /// Process joins the cgroup by writing its pid here
+    pub fn get_procs_path(&self) -> PathBuf {
+        self.path.join("cgroup.procs")
+    }
+
+    /// Kills anything that is left in cgroup & removes it
+    pub async fn remove(self) -> io::Result<()> {
+        // Removal fails till all processes are reaped, anything forked meanwhile is killed next time
+        let mut attempts = 0;
+        loop {
+            self.kill().await?;
+            match fs::remove_dir(&self.path).await {
+                Ok(_) => break Ok(()),
+                Err(e) if attempts < 5 => {
+                    debug!("Retrying removal of cgroup {:?}: {}", self.path, e);
+                    attempts += 1;
+                    tokio::time::delay_for(Duration::from_millis(200)).await;
+                }
+                Err(e) => break Err(e),
+            }
+        }
+    }
+
+    async fn kill(&self) -> io::Result<()> {
+        // Only present from linux 5.14, before that every process has to be killed by us
+        let kill_path = self.path.join("cgroup.kill");
+        if kill_path.exists() {
+            return fs::write(kill_path, "1").await;
+        }
+
+        let procs = fs::read_to_string(self.get_procs_path()).await?;
+        for pid in procs.lines().filter_map(|line| line.trim().parse::<i32>().ok()) {
+            debug!("Killing pid {} left in cgroup {:?}", pid, self.path);
+            let killed = match heim::process::get(pid).await {
+                Ok(process) => process.kill().await,
+                Err(e) => Err(e),
+            };
+            // Process might have exited by itself meanwhile
+            if let Err(e) = killed {
+                debug!("Unable to kill pid {}: {}", pid, e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use std::error::Error;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+use tokio::fs;
+
+use super::corpus_syncer::CorpusSyncer;
+use super::crash_syncer::CrashSyncer;
+use super::{ExecutorConfig, WORKER_LABEL};
+use crate::common::artifacts::setup_artifact;
+use crate::common::executors::{extract_contraint_volume_map, get_container_volume_map, get_worker_uuid};
+use crate::fuzz_driver::{CorpusConfig, CrashConfig};
+use crate::utils::checksum;
+use crate::utils::fs::{mkdir_p, rm_r};
+
+/// Parts shared by container executors. config.cwd is used only to mount a volume at that path &
+/// run command from there, every task gets its own folder on volume map for it.
+pub struct ContainerContext {
+    pub config: ExecutorConfig,
+    pub worker_task_id: Option<i32>,
+
+    // Container is named by identifier
+    pub identifier: String,
+    /// mapped_path (On fuzzy's container) == host_path (On Host) == config.cwd (On target
+    // Path accessible to fuzzy as cwd, that is also cwd for fuzzer
+    // inside container but with a different name
+    pub mapped_cwd: PathBuf,
+    pub host_cwd: PathBuf,
+}
+
+impl ContainerContext {
+    pub fn new(config: ExecutorConfig, worker_task_id: Option<i32>) -> Self {
+        let volume_path = get_container_volume_map();
+        if volume_path.is_err() {
+            panic!("This is bad, volume path doesn't seem to be set!");
+        }
+        let (host_path, mapped_path) = extract_contraint_volume_map(volume_path.unwrap().as_ref());
+
+        // Create unique identifier
+        let mut unique_string = format!("{}", &worker_task_id.as_ref().unwrap_or(&0));
+        unique_string.push_str(config.executable.as_str());
+        for arg in &config.args {
+            unique_string.push_str(arg.as_str());
+        }
+        let identifier = checksum(&unique_string.into_bytes());
+        debug!("Created new identifier for container executor: {}", identifier);
+
+        // Append a folder to both host & mapped path so that we don't collide different container
+        // executor instances
+        let mapped_cwd = mapped_path.as_path().join(&identifier);
+        let host_cwd = host_path.as_path().join(&identifier);
+
+        Self {
+            config,
+            worker_task_id,
+            identifier,
+            mapped_cwd,
+            host_cwd,
+        }
+    }
+
+    /// Create a new working directory just for this task at mapped_path
+    pub async fn setup_cwd(&self) -> Result<(), Box<dyn Error>> {
+        mkdir_p(&self.mapped_cwd).await?;
+
+        if let Some(artifact) = self.config.artifact.as_ref() {
+            setup_artifact(artifact, &self.mapped_cwd).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn create_relative_dirp(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mapped_path = self.mapped_cwd.join(path);
+        mkdir_p(mapped_path.as_path()).await?;
+        Ok(())
+    }
+
+    pub async fn rm_relative_dirp(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mapped_path = self.mapped_cwd.join(path);
+        rm_r(mapped_path.as_path()).await?;
+        Ok(())
+    }
+
+    pub fn get_corpus_syncer(&self, mut config: CorpusConfig) -> Result<CorpusSyncer, Box<dyn Error>> {
+        config.path = self.mapped_cwd.join(config.path).into_boxed_path();
+        Ok(CorpusSyncer::new(config, self.worker_task_id)?)
+    }
+
+    pub fn get_crash_syncer(&self, mut config: CrashConfig) -> Result<CrashSyncer, Box<dyn Error>> {
+        config.path = self.mapped_cwd.join(config.path).into_boxed_path();
+        Ok(CrashSyncer::new(config, self.worker_task_id)?)
+    }
+
+    /// Since we created cwd it should be our uid & gid, container runs as them so that files in cwd
+    /// stay accessible to us
+    pub async fn get_cwd_owner(&self) -> Result<(u32, u32), Box<dyn Error>> {
+        let cwd_metadata = fs::metadata(&self.mapped_cwd).await?;
+        Ok((cwd_metadata.uid(), cwd_metadata.gid()))
+    }
+
+    /// Host path of cwd & where it is mounted inside container
+    pub fn get_cwd_mount(&self) -> Option<(&str, &str)> {
+        Some((self.host_cwd.to_str()?, self.config.cwd.to_str()?))
+    }
+
+    /// Options to `run` of docker compatible clis, image & command are left to caller
+    pub async fn get_run_options(&self, blocking: bool) -> Result<Vec<String>, Box<dyn Error>> {
+        let (uid, gid) = self.get_cwd_owner().await?;
+
+        debug!("Constructing options for container run");
+        let mut options = vec![
+            format!("--user={}:{}", uid, gid),
+            format!("--name={}", self.identifier),
+            format!("--entrypoint={}", self.config.executable),
+        ];
+
+        if blocking == false {
+            options.push("-d".to_owned());
+        } else {
+            // Auto removal of container if blocking
+            options.push("--rm".to_owned());
+            options.push("--attach=stdout".to_owned());
+            options.push("--attach=stderr".to_owned());
+        }
+
+        // Set working directory inside target container & mount cwd volume there
+        if let Some(target_container_cwd) = self.config.cwd.to_str() {
+            options.push(format!("--workdir={}", target_container_cwd));
+        }
+        if let Some((host_cwd, target_container_cwd)) = self.get_cwd_mount() {
+            options.push(format!("--volume={}:{}", host_cwd, target_container_cwd));
+        }
+
+        // Only keys are passed, values are taken from launch environment of cli
+        for (key, _) in self.config.envs.iter() {
+            options.push(format!("-e={}", key));
+        }
+
+        if let Some(worker_uuid) = get_worker_uuid() {
+            options.push(format!("--label={}={}", WORKER_LABEL, worker_uuid));
+        }
+
+        if let Some(cpus) = self.config.get_cpuset_list() {
+            options.push(format!("--cpuset-cpus={}", cpus));
+        }
+
+        // Runtime puts container in its own cgroup, so just pass limits along
+        if let Some(limits) = self.config.limits.as_ref() {
+            if let Some(memory_max) = limits.memory_max {
+                options.push(format!("--memory={}", memory_max));
+            }
+            if let Some(cpu_quota) = limits.cpu_quota {
+                options.push(format!("--cpus={}", cpu_quota));
+            }
+            if let Some(pids_max) = limits.pids_max {
+                options.push(format!("--pids-limit={}", pids_max));
+            }
+            if let Some(blkio_weight) = limits.get_blkio_weight() {
+                options.push(format!("--blkio-weight={}", blkio_weight));
+            }
+        }
+        Ok(options)
+    }
+}
@@ -1,21 +1,30 @@
+use std::collections::HashMap;
 use std::error::Error;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use tracing::{debug, error, info};
+#[cfg(target_os = "linux")]
+use inotify::WatchMask;
+use tracing::{debug, error, info, warn};
 use regex::Regex;
 use tokio::sync::broadcast;
-use tonic::transport::channel::Channel;
 
-use crate::common::corpora::{download_corpus_to_disk, upload_corpus_from_disk, CORPUS_FILE_EXT};
+use crate::common::corpora::{download_corpus_to_disk_cached, new_corpus, CORPUS_FILE_EXT};
+use crate::common::executors::is_disk_low;
+use crate::common::intervals::WORKER_CORPUS_UPLOAD_DEBOUNCE;
+use crate::common::outbox::submit_or_buffer;
 use crate::common::xpc::get_orchestrator_client;
 use crate::fuzz_driver::CorpusConfig;
-use crate::utils::fs::FileWatcher;
-use crate::xpc::orchestrator_client::OrchestratorClient;
+use crate::utils::fs::{read_file, FileWatcher};
+#[cfg(target_os = "linux")]
+use crate::utils::fs::{InotifyFileWatcher, WatchEvent};
+use crate::xpc::OutboxEntry;
 
 /// A file system corpus syncer. Need to convert this into trait when implementing docker
 pub struct CorpusSyncer {
     config: CorpusConfig,
     worker_task_id: Option<i32>,
+    driver: Option<String>,
     last_download: SystemTime,
 }
 
@@ -24,15 +33,21 @@ impl CorpusSyncer {
         Ok(Self {
             config,
             worker_task_id,
+            driver: None,
             last_download: UNIX_EPOCH,
         })
     }
 
+    /// Driver is recorded as provenance for uploaded corpus
+    pub fn set_driver(&mut self, driver: String) {
+        self.driver = Some(driver);
+    }
+
     pub async fn setup_corpus(&mut self) -> Result<(), Box<dyn Error>> {
         debug!("Syncing initial corpus");
         let mut client = get_orchestrator_client().await?;
         // Initial syncing need not get
-        download_corpus_to_disk(
+        download_corpus_to_disk_cached(
             self.config.label.clone(),
             None,
             None,
@@ -42,20 +57,35 @@ impl CorpusSyncer {
             &mut client,
         )
         .await?;
+
+        // Downloaded files carry corpus extension which is never uploaded, so seeds stay in their labels
+        for seed_label in self.config.seed_labels.iter() {
+            debug!("Syncing seed corpus from {}", seed_label);
+            download_corpus_to_disk_cached(
+                seed_label.clone(),
+                None,
+                None,
+                None,
+                UNIX_EPOCH,
+                &self.config.path,
+                &mut client,
+            )
+            .await?;
+        }
         self.last_download = SystemTime::now();
         Ok(())
     }
 
     pub async fn sync_corpus(&self, mut kill_switch: broadcast::Receiver<u8>) -> Result<(), Box<dyn Error>> {
         debug!("Will try to keep corpus in sync at: {:?}", self.config.path);
-        let client = get_orchestrator_client().await?;
 
+        // Clients are taken for every sync, so that backoff & reloaded master settings are picked up
         tokio::select! {
-            _ = self.download(client.clone()) => {
+            _ = self.download() => {
                 error!("Downloading corpus exited first, whaaatt!");
             },
             // Doing this should be very necessary
-            _ = self.upload(UNIX_EPOCH, client.clone(), true), if self.config.upload => {
+            _ = self.watch_upload(), if self.config.upload => {
                 error!("Uploading corpus exited first, whaaatt!");
             },
             _ = kill_switch.recv() => {
@@ -65,19 +95,12 @@ impl CorpusSyncer {
         Ok(())
     }
 
-    async fn upload(
-        &self,
-        last_upload: SystemTime,
-        mut client: OrchestratorClient<Channel>,
-        infinite_loop: bool,
-    ) -> Result<(), Box<dyn Error>> {
+    async fn upload(&self, last_upload: SystemTime, infinite_loop: bool) -> Result<(), Box<dyn Error>> {
         let mut interval = tokio::time::interval(Duration::from_secs(self.config.refresh_interval));
-        // let mut client = client;
         info!("Creating corpus upload sync");
-        let ext_regex = Regex::new(format!(".*\\.{}$", CORPUS_FILE_EXT).as_str()).unwrap();
         let mut watcher = FileWatcher::new(
             &self.config.path,
-            Some(ext_regex),
+            Some(get_downloaded_regex()),
             Some(self.config.upload_filter.clone()),
             last_upload,
         )?;
@@ -87,17 +110,7 @@ impl CorpusSyncer {
             let files = watcher.get_new_files()?;
             info!("Uploading {} new corpus to master", files.len());
             for file_path in files {
-                info!("Uploading new corpus: {:?}", file_path);
-                if let Err(e) = upload_corpus_from_disk(
-                    file_path.as_path(),
-                    self.config.label.clone(),
-                    self.worker_task_id,
-                    &mut client,
-                )
-                .await
-                {
-                    error!("Failed to upload {:?} as corpus: {}", file_path.as_path(), e);
-                }
+                self.upload_file(file_path.as_path()).await;
             }
 
             if infinite_loop {
@@ -109,21 +122,123 @@ impl CorpusSyncer {
         Ok(())
     }
 
-    async fn download(&self, mut client: OrchestratorClient<Channel>) -> Result<(), Box<dyn Error>> {
+    #[cfg(target_os = "linux")]
+    async fn watch_upload(&self) -> Result<(), Box<dyn Error>> {
+        info!("Creating inotify corpus upload sync");
+        // Watch is added before scanning, so that nothing written in between is missed
+        let mut watcher = InotifyFileWatcher::with_mask(
+            &self.config.path,
+            WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO,
+            Some(get_downloaded_regex()),
+            Some(self.config.upload_filter.clone()),
+        )?;
+        let mut scanner = FileWatcher::new(
+            &self.config.path,
+            Some(get_downloaded_regex()),
+            Some(self.config.upload_filter.clone()),
+            UNIX_EPOCH,
+        )?;
+
+        // Files are only uploaded once no new events are seen for them for debounce duration,
+        // as fuzzers can write the same file multiple times
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        for file_path in scanner.get_new_files()? {
+            pending.insert(file_path, Instant::now());
+        }
+
+        let mut interval = tokio::time::interval(WORKER_CORPUS_UPLOAD_DEBOUNCE);
+        loop {
+            tokio::select! {
+                event = watcher.get_event() => {
+                    match event {
+                        Some(WatchEvent::File(file_name)) => {
+                            pending.insert(self.config.path.join(file_name), Instant::now());
+                        },
+                        Some(WatchEvent::Overflow) => {
+                            warn!("Corpus watch overflowed, falling back to scanning {:?}", self.config.path);
+                            for file_path in scanner.get_new_files()? {
+                                pending.insert(file_path, Instant::now());
+                            }
+                        },
+                        None => break,
+                    }
+                },
+                _ = interval.tick() => {
+                    let now = Instant::now();
+                    let settled: Vec<PathBuf> = pending
+                        .iter()
+                        .filter(|(_, seen)| now.duration_since(**seen) >= WORKER_CORPUS_UPLOAD_DEBOUNCE)
+                        .map(|(file_path, _)| file_path.clone())
+                        .collect();
+                    if settled.len() > 0 {
+                        info!("Uploading {} new corpus to master", settled.len());
+                    }
+                    for file_path in settled {
+                        pending.remove(&file_path);
+                        self.upload_file(file_path.as_path()).await;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn watch_upload(&self) -> Result<(), Box<dyn Error>> {
+        self.upload(UNIX_EPOCH, true).await
+    }
+
+    async fn upload_file(&self, file_path: &Path) {
+        info!("Uploading new corpus: {:?}", file_path);
+        let content = match read_file(file_path).await {
+            Ok(content) => content,
+            Err(e) => {
+                error!("Failed to read {:?} for corpus upload: {}", file_path, e);
+                return;
+            }
+        };
+
+        // Buffered if master is unreachable, as fuzzer might not find this input again
+        let corpus = new_corpus(
+            content,
+            self.config.label.clone(),
+            self.worker_task_id,
+            self.driver.clone(),
+        );
+        let mut client = get_orchestrator_client().await.ok();
+        if let Err(e) = submit_or_buffer(OutboxEntry::corpus(corpus), client.as_mut()).await {
+            error!("Failed to upload {:?} as corpus: {}", file_path, e);
+        }
+    }
+
+    async fn download(&self) -> Result<(), Box<dyn Error>> {
         let mut interval = tokio::time::interval(Duration::from_secs(self.config.refresh_interval));
         let mut last_download = self.last_download.clone();
         loop {
             interval.tick().await;
-            let result = download_corpus_to_disk(
-                self.config.label.clone(),
-                self.worker_task_id,
-                None,
-                None,
-                last_download,
-                &self.config.path,
-                &mut client,
-            )
-            .await;
+            // Skipped downloads are picked up later, as last_download doesn't move ahead
+            if is_disk_low().await {
+                warn!(
+                    "Pausing corpus download for {:?} as disk space is low",
+                    self.config.path
+                );
+                continue;
+            }
+            let client = get_orchestrator_client().await.map_err(|e| e.to_string());
+            let result = match client {
+                Ok(mut client) => download_corpus_to_disk_cached(
+                    self.config.label.clone(),
+                    self.worker_task_id,
+                    None,
+                    None,
+                    last_download,
+                    &self.config.path,
+                    &mut client,
+                )
+                .await
+                .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
             // If successful update, set last_updated
             if let Err(e) = result {
                 error!("Download sync job failed: {}", e);
@@ -134,10 +249,15 @@ impl CorpusSyncer {
     }
 
     pub async fn close(self, last_upload: SystemTime) -> Result<(), Box<dyn Error>> {
+        // Uploads are buffered if master is unreachable, so nothing found is lost
         if self.config.upload {
-            let client = get_orchestrator_client().await?;
-            self.upload(last_upload, client, false).await?;
+            self.upload(last_upload, false).await?;
         }
         Ok(())
     }
 }
+
+/// Downloaded corpus are written with corpus extension, they should never be uploaded back
+fn get_downloaded_regex() -> Regex {
+    Regex::new(format!(".*\\.{}$", CORPUS_FILE_EXT).as_str()).unwrap()
+}
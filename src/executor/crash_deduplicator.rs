@@ -1,11 +1,11 @@
 use std::error::Error;
 use std::time::UNIX_EPOCH;
 
-use tracing::info;
+use tracing::{info, warn};
 use tonic::transport::channel::Channel;
 
 use crate::common::{crashes::download_crashes, xpc::get_orchestrator_client};
-use crate::executor;
+use crate::executor::{self, is_timeout};
 use crate::fuzz_driver::CrashConfig;
 use crate::utils::fs::rm_r;
 use crate::xpc::{self, orchestrator_client::OrchestratorClient};
@@ -69,8 +69,15 @@ impl CrashDeduplicator {
                     // Write output to crash file
                     tokio::fs::write(&existing_output_path, output).await?;
 
-                    // Launch command
-                    let output = executor.spawn_blocking().await?;
+                    // Launch command, a comparison that hangs is taken as not duplicate
+                    let output = match executor.spawn_blocking().await {
+                        Ok(output) => output,
+                        Err(e) if is_timeout(e.as_ref()) => {
+                            warn!("Deduplication against crash {} timed out", crash.id);
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
 
                     // Zero exit code means duplicate just like diff command
                     if output.status.success() == true {
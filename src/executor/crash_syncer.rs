@@ -1,32 +1,42 @@
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
 
-use tracing::{debug, error, info};
-use tokio::sync::broadcast;
-use tonic::transport::channel::Channel;
+use tracing::{debug, error, info, warn};
+use tokio::{fs, sync::broadcast};
 
+use super::crash_deduplicator::CrashDeduplicator;
+use super::crash_validator::CrashValidator;
 use crate::common::crashes::upload_crash_from_disk;
 use crate::common::xpc::get_orchestrator_client;
 use crate::fuzz_driver::CrashConfig;
-use crate::xpc::orchestrator_client::OrchestratorClient;
+use crate::utils::fs::FileWatcher;
 
 /// A file system corpus syncer. Need to convert this into trait when implementing docker
 pub struct CrashSyncer {
     config: CrashConfig,
     worker_task_id: Option<i32>,
+
+    // Crashes that reached master or outbox, removed from disk on close
+    uploaded: Mutex<Vec<PathBuf>>,
 }
 
 impl CrashSyncer {
     pub fn new(config: CrashConfig, worker_task_id: Option<i32>) -> Result<Self, Box<dyn Error>> {
-        Ok(Self { config, worker_task_id })
+        Ok(Self {
+            config,
+            worker_task_id,
+            uploaded: Mutex::new(Vec::new()),
+        })
     }
 
     pub async fn upload_crashes(&self, mut kill_switch: broadcast::Receiver<u8>) -> Result<(), Box<dyn Error>> {
         debug!("Will try to keep crashes in sync at: {:?}", self.config.path);
-        let client = get_orchestrator_client().await?;
 
-        // Create necessary clones and pass along for upload sync if upload enabled
+        // Crashes are buffered while master is unreachable, so no client is needed to start watching
         tokio::select! {
-            result = self.upload(client) => {
+            result = self.upload() => {
                 error!("Crash upload sync job failed: {:?}", result);
             },
             _ = kill_switch.recv() => {}
@@ -38,51 +48,97 @@ impl CrashSyncer {
     }
 
     #[cfg(target_os = "linux")]
-    async fn upload(&self, client: OrchestratorClient<Channel>) -> Result<(), Box<dyn Error>> {
-        let mut client = client;
+    async fn upload(&self) -> Result<(), Box<dyn Error>> {
         info!("Creating crash upload sync");
         let mut watcher =
             crate::utils::fs::InotifyFileWatcher::new(&self.config.path, Some(self.config.filter.clone()))?;
-        let validator = super::crash_validator::CrashValidator::new(self.config.clone(), self.worker_task_id)?;
-        let deduplicator = super::crash_deduplicator::CrashDeduplicator::new(self.config.clone(), self.worker_task_id)?;
+        let validator = CrashValidator::new(self.config.clone(), self.worker_task_id)?;
+        let deduplicator = CrashDeduplicator::new(self.config.clone(), self.worker_task_id)?;
 
         while let Some(file) = watcher.get_new_file().await {
             // Match user provided match pattern
-            let file_path = self.config.path.clone();
-            let file_path = file_path.join(file);
-
-            // Verify crash if profile mandates it
-            let (output, verified) = match validator.validate_crash(file_path.as_path()).await {
-                Ok((output, verified)) => (output, verified),
-                Err(e) => {
-                    error!("Unable to validate crash {:?} due to error: {}", file_path, e);
-                    (None, false)
-                }
-            };
-
-            let mut dup_crash_id = None;
-            if let Some(out) = output.as_ref() {
-                dup_crash_id = deduplicator.dedup_crash(out).await?;
-            }
-
-            info!("Uploading new crash: {:?}", file_path);
-            upload_crash_from_disk(
-                file_path.as_path(),
-                self.config.label.clone(),
-                verified,
-                output,
-                self.worker_task_id,
-                dup_crash_id,
-                &mut client,
-            )
-            .await?
+            let file_path = self.config.path.join(file);
+            self.upload_crash(file_path, &validator, &deduplicator).await;
         }
         Ok(())
     }
 
     #[cfg(not(target_os = "linux"))]
-    async fn upload(&self, client: OrchestratorClient<Channel>) -> Result<(), Box<dyn Error>> {
+    async fn upload(&self) -> Result<(), Box<dyn Error>> {
         error!("Crash syncer is not ported yet to work on non linux systems");
         Ok(())
     }
+
+    /// Uploads crashes already lying in crash directory, like ones a dead worker never got to
+    pub async fn upload_existing_crashes(&self) -> Result<(), Box<dyn Error>> {
+        let mut scanner = FileWatcher::new(&self.config.path, None, Some(self.config.filter.clone()), UNIX_EPOCH)?;
+        let validator = CrashValidator::new(self.config.clone(), self.worker_task_id)?;
+        let deduplicator = CrashDeduplicator::new(self.config.clone(), self.worker_task_id)?;
+        for file_path in scanner.get_new_files()? {
+            self.upload_crash(file_path, &validator, &deduplicator).await;
+        }
+        Ok(())
+    }
+
+    async fn upload_crash(&self, file_path: PathBuf, validator: &CrashValidator, deduplicator: &CrashDeduplicator) {
+        // Verify crash if profile mandates it
+        let (mut output, mut verified) = match validator.validate_crash(file_path.as_path()).await {
+            Ok((output, verified)) => (output, verified),
+            Err(e) => {
+                error!("Unable to validate crash {:?} due to error: {}", file_path, e);
+                (None, false)
+            }
+        };
+
+        // Crash reproducing on any build variant is a verified crash
+        let variants = validator.validate_crash_variants(file_path.as_path()).await;
+        if let Some(variant) = variants.iter().find(|v| v.verified) {
+            verified = true;
+            // Without a primary validation, first reproducing variant's output is used for dedup
+            if output.is_none() {
+                output = Some(variant.output.clone());
+            }
+        }
+
+        // Deduplication needs master, without it crash is uploaded as not known to be a duplicate
+        let mut dup_crash_id = None;
+        if let Some(out) = output.as_ref() {
+            match deduplicator.dedup_crash(out).await {
+                Ok(crash_id) => dup_crash_id = crash_id,
+                Err(e) => warn!("Unable to deduplicate crash {:?}: {}", file_path, e),
+            }
+        }
+
+        info!("Uploading new crash: {:?}", file_path);
+        let mut client = get_orchestrator_client().await.ok();
+        if let Err(e) = upload_crash_from_disk(
+            file_path.as_path(),
+            self.config.label.clone(),
+            verified,
+            output,
+            self.worker_task_id,
+            dup_crash_id,
+            variants,
+            client.as_mut(),
+        )
+        .await
+        {
+            error!("Unable to upload crash {:?}: {}", file_path, e);
+        } else {
+            self.uploaded.lock().unwrap().push(file_path);
+        }
+    }
+
+    /// Crash directory might be cwd itself, so only crashes that were uploaded are removed. Fuzzers skip
+    /// saving a crash again if its file exists, which is why this waits till task is done
+    pub async fn close(self) -> Result<(), Box<dyn Error>> {
+        let uploaded = self.uploaded.into_inner().unwrap();
+        debug!("Removing {} uploaded crashes", uploaded.len());
+        for file_path in uploaded.iter() {
+            if let Err(e) = fs::remove_file(file_path).await {
+                warn!("Unable to remove uploaded crash {:?}: {}", file_path, e);
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,12 +1,19 @@
 use std::error::Error;
 use std::path::Path;
 
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::executor;
+use crate::executor::{self, is_timeout, ExecutorConfig};
 use crate::fuzz_driver::CrashConfig;
 use crate::utils::fs::rm_r;
 
+/// Outcome of reproducing a crash against one of the build variants
+pub struct VariantValidation {
+    pub variant: String,
+    pub output: String,
+    pub verified: bool,
+}
+
 /// A file system corpus syncer. Need to convert this into trait when implementing docker
 pub struct CrashValidator {
     config: CrashConfig,
@@ -19,46 +26,91 @@ impl CrashValidator {
     }
 
     pub async fn validate_crash(&self, crash: &Path) -> Result<(Option<String>, bool), Box<dyn Error>> {
-        if let Some(mut exec_config) = self.config.validate.clone() {
-            // Create a temporary name that will be passed as argv[-1]
-            let relative_file_name = "crash.fuzzy";
-            exec_config.args.push(relative_file_name.to_owned());
+        if let Some(exec_config) = self.config.validate.clone() {
+            let (output, verified) = self.run(exec_config, crash).await?;
+            Ok((Some(output), verified))
+        } else {
+            info!("Not validating crash {:?} as no validate in profile", crash);
+            Ok((None, false))
+        }
+    }
 
-            // Create executor
-            let mut executor = executor::new(exec_config, self.worker_task_id);
-            executor.setup().await?;
+    /// Reproduces crash against every build variant, a failing variant doesn't stop others
+    pub async fn validate_crash_variants(&self, crash: &Path) -> Vec<VariantValidation> {
+        let mut validations = Vec::new();
+        for variant in self.config.variants.iter() {
+            match self.run(variant.validate.clone(), crash).await {
+                Ok((output, verified)) => validations.push(VariantValidation {
+                    variant: variant.name.clone(),
+                    output,
+                    verified,
+                }),
+                Err(e) => warn!(
+                    "Unable to validate crash {:?} on {} variant: {}",
+                    crash, variant.name, e
+                ),
+            }
+        }
+        validations
+    }
 
-            // Copy crash file into cwd of validate
-            let cwd = executor.get_cwd_path();
-            let temp_path = cwd.join(relative_file_name);
-            // Copy file into cwd
-            tokio::fs::copy(crash, temp_path.as_path()).await?;
+    async fn run(&self, mut exec_config: ExecutorConfig, crash: &Path) -> Result<(String, bool), Box<dyn Error>> {
+        // Create a temporary name that will be passed as argv[-1]
+        let relative_file_name = "crash.fuzzy";
+        exec_config.args.push(relative_file_name.to_owned());
 
-            let output = executor.spawn_blocking().await?;
+        // Create executor
+        let mut executor = executor::new(exec_config, self.worker_task_id);
+        executor.setup().await?;
 
-            // Any non zero exit code, we mark crash as verified
-            let verified = output.status.success() == false;
+        // Copy crash file into cwd of validate
+        let cwd = executor.get_cwd_path();
+        let temp_path = cwd.join(relative_file_name);
+        // Copy file into cwd
+        tokio::fs::copy(crash, temp_path.as_path()).await?;
 
-            let stdout = std::str::from_utf8(&output.stdout)?;
-            let stderr = std::str::from_utf8(&output.stderr)?;
+        let output = match executor.spawn_blocking().await {
+            Ok(output) => Ok(output),
+            Err(e) if is_timeout(e.as_ref()) => Err(e.to_string()),
+            Err(e) => return Err(e),
+        };
 
-            // Join stdout/stderr for now as output
-            let output = format!(
-                "STDOUT\n\
-                                  ------\n\
-                                  {}\n\
-                                  STDERR\n\
-                                  ------\n\
-                                  {}\n",
-                stdout, stderr
-            );
+        let (output, verified) = match output {
+            Ok(output) => {
+                // Any non zero exit code, we mark crash as verified
+                let verified = output.status.success() == false;
 
-            // Remove cwd
-            rm_r(&cwd).await?;
-            Ok((Some(output), verified))
-        } else {
-            info!("Not validating crash {:?} as no validate in profile", crash);
-            Ok((None, false))
-        }
+                // Sanitizer reports can carry arbitrary bytes from the crash
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+
+                // Join stdout/stderr for now as output
+                let output = format!(
+                    "STDOUT\n\
+                                      ------\n\
+                                      {}\n\
+                                      STDERR\n\
+                                      ------\n\
+                                      {}\n",
+                    stdout, stderr
+                );
+                (output, verified)
+            }
+            Err(e) => {
+                // Hanging repro is not a verified crash, but keep a distinct output for triage
+                warn!("Validation of crash {:?} timed out", crash);
+                let output = format!(
+                    "TIMEOUT\n\
+                                      -------\n\
+                                      {}\n",
+                    e
+                );
+                (output, false)
+            }
+        };
+
+        // Remove cwd
+        rm_r(&cwd).await?;
+        Ok((output, verified))
     }
 }
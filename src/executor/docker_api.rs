@@ -0,0 +1,363 @@
+use std::env;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+use bytes::{Bytes, BytesMut};
+use hyper::{body::HttpBody, Body, Client, Method, Request, Response, StatusCode};
+use hyperlocal::{UnixClientExt, UnixConnector};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+use tracing::{debug, error, trace};
+use tokio::sync::mpsc;
+
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+// Docker stream type identifiers in multiplexed streams
+pub const STDOUT_STREAM: u8 = 1;
+pub const STDERR_STREAM: u8 = 2;
+
+/// Minimal Docker Engine API client over unix socket, only what our executor needs
+#[derive(Clone)]
+pub struct DockerClient {
+    socket: PathBuf,
+    client: Client<UnixConnector, Body>,
+}
+
+impl DockerClient {
+    pub fn new() -> Self {
+        // Respect DOCKER_HOST like docker cli does, as long as it is a unix socket
+        let socket = match env::var("DOCKER_HOST") {
+            Ok(host) if host.starts_with("unix://") => PathBuf::from(host.trim_start_matches("unix://")),
+            _ => PathBuf::from(DOCKER_SOCKET),
+        };
+        debug!("Using docker socket at {:?}", socket);
+        Self {
+            socket,
+            client: Client::unix(),
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Response<Body>, Box<dyn Error>> {
+        trace!("Docker api request: {} {}", method, path);
+        let uri: hyper::Uri = hyperlocal::Uri::new(&self.socket, path).into();
+        let builder = Request::builder().method(method).uri(uri);
+
+        let request = match body {
+            Some(b) => builder
+                .header("Content-Type", "application/json")
+                .body(Body::from(serde_json::to_vec(&b)?))?,
+            None => builder.body(Body::empty())?,
+        };
+        Ok(self.client.request(request).await?)
+    }
+
+    /// Same as request but any non success status is converted to an error with daemon's message
+    async fn request_ok(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Response<Body>, Box<dyn Error>> {
+        let response = self.request(method, path, body).await?;
+        let status = response.status();
+        if status.is_success() || status == StatusCode::NOT_MODIFIED {
+            Ok(response)
+        } else {
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let message = serde_json::from_slice::<Value>(&body)
+                .ok()
+                .and_then(|v| v["message"].as_str().map(|s| s.to_owned()))
+                .unwrap_or_else(|| format!("{:?}", body));
+            Err(Box::new(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Docker api returned {} for {}: {}", status, path, message),
+            )))
+        }
+    }
+
+    async fn request_json(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, Box<dyn Error>> {
+        let response = self.request_ok(method, path, body).await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    pub async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        self.request_ok(Method::GET, "/_ping", None).await?;
+        Ok(())
+    }
+
+    pub async fn pull_image(&self, image: &str) -> Result<(), Box<dyn Error>> {
+        let (name, tag) = split_image_reference(image);
+        let path = format!("/images/create?fromImage={}&tag={}", encode(name), encode(tag));
+        let response = self.request_ok(Method::POST, &path, None).await?;
+
+        // Progress is streamed as json objects, failures show up as an error key in them
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        for line in body.split(|b| *b == b'\n') {
+            if let Ok(progress) = serde_json::from_slice::<Value>(line) {
+                if let Some(e) = progress["error"].as_str() {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unable to pull image {}: {}", image, e),
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn create_container(&self, name: &str, config: Value) -> Result<String, Box<dyn Error>> {
+        let path = format!("/containers/create?name={}", encode(name));
+        let response = self.request_json(Method::POST, &path, Some(config)).await?;
+        let id = response["Id"].as_str().unwrap_or(name).to_owned();
+        debug!("Created container {} with id {}", name, id);
+        Ok(id)
+    }
+
+    /// Names of all containers (running or not) having label, like key=value
+    pub async fn list_containers(&self, label: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let filters = json!({ "label": [label] });
+        let path = format!("/containers/json?all=true&filters={}", encode(&filters.to_string()));
+        let response = self.request_json(Method::GET, &path, None).await?;
+
+        let mut names = Vec::new();
+        for container in response.as_array().unwrap_or(&Vec::new()) {
+            // Names are prefixed with a slash & first one is the one given at creation
+            if let Some(name) = container["Names"][0].as_str() {
+                names.push(name.trim_start_matches('/').to_owned());
+            }
+        }
+        Ok(names)
+    }
+
+    pub async fn start_container(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let path = format!("/containers/{}/start", encode(id));
+        self.request_ok(Method::POST, &path, None).await?;
+        Ok(())
+    }
+
+    pub async fn stop_container(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let path = format!("/containers/{}/stop", encode(id));
+        self.request_ok(Method::POST, &path, None).await?;
+        Ok(())
+    }
+
+    /// Removes the container even if running, missing containers are not an error
+    pub async fn remove_container(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let path = format!("/containers/{}?force=true", encode(id));
+        let response = self.request(Method::DELETE, &path, None).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            debug!("Container {} doesn't exist to remove", id);
+            return Ok(());
+        }
+        if !response.status().is_success() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Unable to remove container {}: {}", id, response.status()),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Removes a left over container with same name, unless it is still running as that
+    /// belongs to someone else & replacing it would kill their task
+    pub async fn remove_stale_container(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let path = format!("/containers/{}/json", encode(id));
+        let response = self.request(Method::GET, &path, None).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let state: Value = serde_json::from_slice(&body)?;
+        if state["State"]["Running"].as_bool() != Some(false) {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Container {} already exists & is running", id),
+            )));
+        }
+        debug!("Removing stale container {}", id);
+        self.remove_container(id).await
+    }
+
+    pub async fn inspect_container(&self, id: &str) -> Result<Value, Box<dyn Error>> {
+        let path = format!("/containers/{}/json", encode(id));
+        self.request_json(Method::GET, &path, None).await
+    }
+
+    /// Blocks till container exits and returns exit code
+    pub async fn wait_container(&self, id: &str) -> Result<i64, Box<dyn Error>> {
+        let path = format!("/containers/{}/wait", encode(id));
+        let response = self.request_json(Method::POST, &path, None).await?;
+        Ok(response["StatusCode"].as_i64().unwrap_or(-1))
+    }
+
+    /// Listens on events stream for container death and returns exit code
+    pub async fn wait_for_exit(&self, id: &str) -> Result<i64, Box<dyn Error>> {
+        let filters = json!({
+            "type": ["container"],
+            "container": [id],
+            "event": ["die"],
+        });
+        let path = format!("/events?filters={}", encode(&filters.to_string()));
+        let mut body = self.request_ok(Method::GET, &path, None).await?.into_body();
+
+        // Container might have died before we started listening
+        let state = self.inspect_container(id).await?;
+        if state["State"]["Running"].as_bool() != Some(true) {
+            return Ok(state["State"]["ExitCode"].as_i64().unwrap_or(-1));
+        }
+
+        let mut buffer = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            buffer.extend_from_slice(&chunk?);
+            if let Some(i) = buffer.iter().position(|b| *b == b'\n') {
+                let line = buffer.split_to(i + 1);
+                let event: Value = serde_json::from_slice(&line)?;
+                debug!("Received docker event: {}", event);
+                let exit_code = event["Actor"]["Attributes"]["exitCode"].as_str().unwrap_or("-1");
+                return Ok(exit_code.parse::<i64>().unwrap_or(-1));
+            }
+        }
+
+        Err(Box::new(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Docker events stream ended before container {} exited", id),
+        )))
+    }
+
+    /// Full logs of a stream for an exited container
+    pub async fn get_logs(&self, id: &str, stream: u8) -> Result<Vec<u8>, Box<dyn Error>> {
+        let response = self
+            .request_ok(Method::GET, &logs_path(id, stream, false), None)
+            .await?;
+        let mut buffer = BytesMut::from(&hyper::body::to_bytes(response.into_body()).await?[..]);
+
+        let mut logs = Vec::new();
+        while let Some((_, payload)) = next_frame(&mut buffer) {
+            logs.extend_from_slice(&payload);
+        }
+        Ok(logs)
+    }
+
+    /// Follows logs of a stream and forwards them to sender till container exits or receiver is dropped
+    pub async fn follow_logs(
+        &self,
+        id: &str,
+        stream: u8,
+        mut sender: mpsc::Sender<io::Result<Bytes>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let response = self.request_ok(Method::GET, &logs_path(id, stream, true), None).await?;
+        let mut body = response.into_body();
+
+        let mut buffer = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            buffer.extend_from_slice(&chunk?);
+            while let Some((_, payload)) = next_frame(&mut buffer) {
+                if sender.send(Ok(payload)).await.is_err() {
+                    debug!("Log receiver for container {} dropped", id);
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Spawns log following off & returns receiving side of it
+    pub fn spawn_follow_logs(&self, id: String, stream: u8) -> mpsc::Receiver<io::Result<Bytes>> {
+        let (sender, receiver) = mpsc::channel(100);
+        let client = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.follow_logs(&id, stream, sender).await {
+                error!("Following logs of container {} failed: {}", id, e);
+            }
+        });
+        receiver
+    }
+}
+
+/// Splits image reference into name & tag (or digest) for pull. Without a tag, docker pulls all tags of
+/// the image. Registry port shows up as a colon before last slash, so only colons after it start a tag.
+fn split_image_reference(image: &str) -> (&str, &str) {
+    if let Some(i) = image.find('@') {
+        return (&image[..i], &image[i + 1..]);
+    }
+    let name_start = image.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match image[name_start..].rfind(':') {
+        Some(i) => (&image[..name_start + i], &image[name_start + i + 1..]),
+        None => (image, "latest"),
+    }
+}
+
+fn encode(s: &str) -> String {
+    utf8_percent_encode(s, NON_ALPHANUMERIC).to_string()
+}
+
+fn logs_path(id: &str, stream: u8, follow: bool) -> String {
+    format!(
+        "/containers/{}/logs?follow={}&stdout={}&stderr={}",
+        encode(id),
+        follow,
+        stream == STDOUT_STREAM,
+        stream == STDERR_STREAM
+    )
+}
+
+/// Containers without tty get stdout & stderr multiplexed into frames, each with an 8 byte
+/// header having stream type at 0 and big endian payload size at 4..8
+fn next_frame(buffer: &mut BytesMut) -> Option<(u8, Bytes)> {
+    if buffer.len() < 8 {
+        return None;
+    }
+    let size = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+    if buffer.len() < 8 + size {
+        return None;
+    }
+    let mut frame = buffer.split_to(8 + size);
+    let stream = frame[0];
+    Some((stream, frame.split_off(8).freeze()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_next_frame() {
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(&[STDOUT_STREAM, 0, 0, 0, 0, 0, 0, 3]);
+        buffer.extend_from_slice(b"out");
+        buffer.extend_from_slice(&[STDERR_STREAM, 0, 0, 0, 0, 0, 0, 5]);
+        buffer.extend_from_slice(b"er");
+
+        assert_eq!(
+            next_frame(&mut buffer),
+            Some((STDOUT_STREAM, Bytes::from_static(b"out")))
+        );
+        // Partial frame stays in buffer till rest of it arrives
+        assert_eq!(next_frame(&mut buffer), None);
+        buffer.extend_from_slice(b"ror");
+        assert_eq!(
+            next_frame(&mut buffer),
+            Some((STDERR_STREAM, Bytes::from_static(b"error")))
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_split_image_reference() {
+        assert_eq!(split_image_reference("ubuntu"), ("ubuntu", "latest"));
+        assert_eq!(split_image_reference("ubuntu:20.04"), ("ubuntu", "20.04"));
+        assert_eq!(
+            split_image_reference("registry:5000/fuzzers/afl"),
+            ("registry:5000/fuzzers/afl", "latest")
+        );
+        assert_eq!(
+            split_image_reference("registry:5000/fuzzers/afl:2.57b"),
+            ("registry:5000/fuzzers/afl", "2.57b")
+        );
+        assert_eq!(
+            split_image_reference("registry:5000/afl@sha256:abcd"),
+            ("registry:5000/afl", "sha256:abcd")
+        );
+    }
+}
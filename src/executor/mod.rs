@@ -1,32 +1,47 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::future::Future;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use tracing::debug;
+use tracing::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 // use serde_regex::{Serialize, Deserialize};
 use tokio::{
-    io::{BufReader, Lines},
-    process::{ChildStderr, ChildStdout},
+    io::{AsyncRead, BufReader},
     sync::broadcast,
 };
 
+use crate::common::executors::{extract_contraint_volume_map, get_container_volume_map, get_worker_uuid};
+use crate::common::intervals::WORKER_PROBE_TIMEOUT;
 use crate::fuzz_driver::{CorpusConfig, CrashConfig};
+use crate::utils::fs::rm_r;
 use corpus_syncer::CorpusSyncer;
 use crash_syncer::CrashSyncer;
 
 // Both of filesystem variants, need to change
+mod cgroup;
+mod container;
 pub mod corpus_syncer;
 pub mod crash_deduplicator;
 pub mod crash_syncer;
 pub mod crash_validator;
 mod docker;
+mod docker_api;
 mod native;
+pub mod output_logger;
+mod podman;
+
+// Label of containers holding uuid of worker that started them
+const WORKER_LABEL: &str = "fuzzy.worker";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ExecutorEnum {
     Native,
     Docker,
+    Podman,
+    Sandbox,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -35,7 +50,7 @@ pub struct ExecutorConfig {
 
     pub cpus: i32,
 
-    // Only used if executor is docker
+    // Only used if executor is docker or podman
     #[serde(default)]
     pub image: String,
 
@@ -48,8 +63,84 @@ pub struct ExecutorConfig {
 
     #[serde(default)]
     pub envs: HashMap<String, String>,
+
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+
+    // Cores to pin process to, assigned by worker's task manager
+    #[serde(default)]
+    pub cpuset: Option<Vec<usize>>,
+
+    // Seconds after which a blocking run is killed, waits forever if not set
+    #[serde(default)]
+    pub timeout: Option<u64>,
+
+    #[serde(default)]
+    pub artifact: Option<ArtifactConfig>,
+}
+
+/// Bundle uploaded to master, unpacked into cwd before spawn
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtifactConfig {
+    pub name: String,
+
+    // Latest upload of name if not set
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl ExecutorConfig {
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    /// Cpu list format as understood by taskset & container runtimes, like 0,1,2
+    pub fn get_cpuset_list(&self) -> Option<String> {
+        self.cpuset
+            .as_ref()
+            .map(|cores| cores.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(","))
+    }
+}
+
+/// Enforced by cgroup v2 for native executors & by container runtime for others
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ResourceLimits {
+    // In bytes
+    #[serde(default)]
+    pub memory_max: Option<u64>,
+
+    // Number of cpus worth of time, can be fractional like 1.5
+    #[serde(default)]
+    pub cpu_quota: Option<f64>,
+
+    #[serde(default)]
+    pub pids_max: Option<u64>,
+
+    // Relative weight between 1 & 10000 as in cgroup v2 io.weight
+    #[serde(default)]
+    pub io_weight: Option<u16>,
 }
 
+impl ResourceLimits {
+    /// Container runtimes still take io weight in cgroup v1 blkio range of 10 to 1000. Like runc,
+    /// defaults of both (100 & 500) are kept aligned & either side of them is scaled linearly
+    pub fn get_blkio_weight(&self) -> Option<u16> {
+        self.io_weight.map(|w| {
+            let w = w.max(1).min(10000) as u32;
+            let blkio_weight = if w <= 100 {
+                10 + (w - 1) * 490 / 99
+            } else {
+                500 + (w - 100) * 500 / 9900
+            };
+            blkio_weight as u16
+        })
+    }
+}
+
+/// Buffered reader over output of a spawned process, irrespective of where it comes from. Output
+/// can be anything, so read it with output_logger::read_line rather than as utf-8 lines
+pub type OutputReader = BufReader<Box<dyn AsyncRead + Send + Unpin>>;
+
 // Only fear was tokio::process::Child which seems to obey Send so we do too
 #[tonic::async_trait]
 pub trait Executor: Send + Sync {
@@ -63,13 +154,13 @@ pub trait Executor: Send + Sync {
 
     /// Actually responsible for launching of the process
     async fn spawn(&mut self) -> Result<(), Box<dyn Error>>;
+    /// Kills the process if it runs past configured timeout, check result with is_timeout()
     async fn spawn_blocking(&mut self) -> Result<std::process::Output, Box<dyn Error>>;
 
-    // TODO: Improve these ChildStdout signatures to support other executors
     /// Get stdout reader
-    fn get_stdout_reader(&mut self) -> Option<Lines<BufReader<ChildStdout>>>;
+    fn get_stdout_reader(&mut self) -> Option<OutputReader>;
     /// Get stderr reader
-    fn get_stderr_reader(&mut self) -> Option<Lines<BufReader<ChildStderr>>>;
+    fn get_stderr_reader(&mut self) -> Option<OutputReader>;
 
     // TODO: Switch to generic trait based returns so we can swap file monitors
     // fn get_file_watcher(&self, path: Path) -> Box<dyn file_watcher::FileWatcher>;
@@ -79,12 +170,38 @@ pub trait Executor: Send + Sync {
     // Get absolute path for relative to cwd
     fn get_cwd_path(&self) -> PathBuf;
 
+    /// Pid or container name of spawned process, reported to master
+    fn get_process(&self) -> Option<String>;
+
     async fn wait(&self, mut kill_switch: broadcast::Receiver<u8>) -> Result<(), Box<dyn Error>>;
 
     // Clean up all spawned children
     async fn close(mut self: Box<Self>) -> Result<(), Box<dyn Error>>;
 }
 
+/// Waits on future for at most timeout, elapsing is reported as an error of kind TimedOut
+pub async fn with_timeout<T, F>(future: F, timeout: Option<Duration>) -> io::Result<T>
+where
+    F: Future<Output = T>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Process didn't exit within {} seconds", timeout.as_secs()),
+            )
+        }),
+        None => Ok(future.await),
+    }
+}
+
+/// Whether spawn_blocking failed because process was killed after running past its timeout
+pub fn is_timeout(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<io::Error>()
+        .map(|e| e.kind() == io::ErrorKind::TimedOut)
+        .unwrap_or(false)
+}
+
 pub fn new(config: ExecutorConfig, worker_task_id: Option<i32>) -> Box<dyn Executor> {
     match config.executor {
         ExecutorEnum::Native => {
@@ -95,5 +212,91 @@ pub fn new(config: ExecutorConfig, worker_task_id: Option<i32>) -> Box<dyn Execu
             debug!("Creating docker executor");
             Box::new(docker::DockerExecutor::new(config, worker_task_id))
         }
+        ExecutorEnum::Podman => {
+            debug!("Creating podman executor");
+            Box::new(podman::PodmanExecutor::new(config, worker_task_id))
+        }
+        ExecutorEnum::Sandbox => {
+            debug!("Creating sandboxed native executor");
+            Box::new(native::NativeExecutor::new_sandboxed(config, worker_task_id))
+        }
+    }
+}
+
+/// Probes if executor can be used on this host, reported to master as a worker capability. A hung
+/// daemon would stall heartbeats, so probes taking too long count as unusable
+pub async fn is_usable(executor: &ExecutorEnum) -> bool {
+    let usable = match executor {
+        ExecutorEnum::Native | ExecutorEnum::Sandbox => return true,
+        ExecutorEnum::Docker => {
+            let client = docker_api::DockerClient::new();
+            tokio::time::timeout(WORKER_PROBE_TIMEOUT, client.ping())
+                .await
+                .map(|ping| ping.is_ok())
+        }
+        ExecutorEnum::Podman => tokio::time::timeout(WORKER_PROBE_TIMEOUT, podman::is_podman_usable()).await,
+    };
+    usable.unwrap_or_else(|_| {
+        warn!(
+            "Probing {:?} executor timed out after {:?}",
+            executor, WORKER_PROBE_TIMEOUT
+        );
+        false
+    })
+}
+
+/// Containers are detached, so they keep running if worker dies & would conflict by name with
+/// worker tasks started after restart. Removes those left behind by this worker along with their cwd.
+/// Fuzzer containers are already torn down by salvaging tasks, so what is left are ones of crash
+/// validation & stats whose cwd only holds copies. Cwd of a container that couldn't be removed is kept
+pub async fn remove_orphaned_containers() -> Result<(), Box<dyn Error>> {
+    let worker_uuid = match get_worker_uuid() {
+        Some(worker_uuid) => worker_uuid,
+        None => return Ok(()),
+    };
+
+    let mut orphans = Vec::new();
+    if is_usable(&ExecutorEnum::Docker).await {
+        orphans.extend(docker::remove_worker_containers(&worker_uuid).await?);
+    }
+    if is_usable(&ExecutorEnum::Podman).await {
+        orphans.extend(podman::remove_worker_containers(&worker_uuid).await?);
+    }
+    if orphans.is_empty() {
+        return Ok(());
+    }
+    info!("Removed {} orphaned containers of earlier run", orphans.len());
+
+    // Container cwd is named after container, only removed ones are returned
+    let (_, mapped_path) = extract_contraint_volume_map(&get_container_volume_map()?);
+    for name in orphans.iter() {
+        let mapped_cwd = mapped_path.join(name);
+        if mapped_cwd.is_dir() {
+            rm_r(&mapped_cwd).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_get_blkio_weight() {
+        let blkio_weight = |io_weight| {
+            ResourceLimits {
+                io_weight,
+                ..Default::default()
+            }
+            .get_blkio_weight()
+        };
+        assert_eq!(blkio_weight(None), None);
+        assert_eq!(blkio_weight(Some(0)), Some(10));
+        assert_eq!(blkio_weight(Some(1)), Some(10));
+        assert_eq!(blkio_weight(Some(50)), Some(252));
+        assert_eq!(blkio_weight(Some(100)), Some(500));
+        assert_eq!(blkio_weight(Some(5050)), Some(750));
+        assert_eq!(blkio_weight(Some(10000)), Some(1000));
+        assert_eq!(blkio_weight(Some(u16::MAX)), Some(1000));
     }
 }
@@ -5,21 +5,54 @@ use std::process::Stdio;
 
 use tracing::{debug, error};
 use tokio::{
-    io::{AsyncBufReadExt, BufReader, Lines},
-    process::{Child, ChildStderr, ChildStdout, Command},
+    io::{AsyncRead, BufReader},
+    process::{Child, Command},
     sync::broadcast,
 };
+use uuid::Uuid;
 
+use super::cgroup::{Cgroup, CGROUP_JOIN_SCRIPT};
 use super::corpus_syncer::CorpusSyncer;
 use super::crash_syncer::CrashSyncer;
-use super::ExecutorConfig;
+use super::{with_timeout, ExecutorConfig, OutputReader};
+use crate::common::artifacts::setup_artifact;
 use crate::fuzz_driver::{CorpusConfig, CrashConfig};
 use crate::utils::fs::{mkdir_p, rm_r};
 
+// Runs as root of a fresh user namespace. Whole filesystem is bound recursively at a scratch dir
+// inside cwd & every mount there is made read-only (keeping locked flags like nosuid), then only cwd
+// is bound writable over it along with a private /tmp. After pivot_root into it, all capabilities
+// are dropped before exec'ing into target. Args are passed as positional parameters so nothing
+// needs quoting
+const SANDBOX_SCRIPT: &str = r#"set -e
+cwd=$(pwd -P)
+target=$(command -v "$1")
+shift
+root="$cwd/.fuzzy_sandbox"
+mkdir -p "$root"
+mount --rbind / "$root"
+mount --make-rprivate "$root"
+awk '{ print $5, $6 }' /proc/self/mountinfo | while read -r m o; do
+    m=$(printf '%b' "$m")
+    case "$m" in "$root" | "$root"/*) mount -o "remount,bind,ro${o#r[wo]}" "$m" ;; esac
+done
+mount -t tmpfs sandbox "$root/tmp"
+mkdir -p "$root$cwd"
+mount --bind "$cwd" "$root$cwd"
+cd "$root"
+pivot_root . .
+umount -l .
+cd "$cwd"
+exec setpriv --no-new-privs --inh-caps=-all --bounding-set=-all -- "$target" "$@""#;
+
 pub struct NativeExecutor {
     config: ExecutorConfig,
     child: Option<Child>,
     worker_task_id: Option<i32>,
+    // Run inside fresh user, mount, pid & network namespaces
+    sandbox: bool,
+    // Present only when limits are configured, one per spawned process
+    cgroup: Option<Cgroup>,
 }
 
 #[tonic::async_trait]
@@ -29,6 +62,10 @@ impl super::Executor for NativeExecutor {
 
         // Check if cwd exists, if not create
         mkdir_p(&self.config.cwd).await?;
+
+        if let Some(artifact) = self.config.artifact.as_ref() {
+            setup_artifact(artifact, &self.config.cwd).await?;
+        }
         Ok(())
     }
 
@@ -68,6 +105,11 @@ impl super::Executor for NativeExecutor {
 
     async fn spawn(&mut self) -> Result<(), Box<dyn Error>> {
         debug!("Launching child process");
+        // Cgroup of previous process if respawning
+        if let Some(cgroup) = self.cgroup.take() {
+            cgroup.remove().await?;
+        }
+        self.cgroup = self.create_cgroup().await?;
         let mut cmd = self.create_cmd();
         let child = cmd.spawn()?;
         self.child = Some(child);
@@ -75,19 +117,29 @@ impl super::Executor for NativeExecutor {
     }
 
     async fn spawn_blocking(&mut self) -> Result<std::process::Output, Box<dyn Error>> {
-        let mut cmd = self.create_cmd();
-        Ok(cmd.spawn()?.wait_with_output().await?)
+        let cgroup = self.create_cgroup().await?;
+        let mut cmd = self.create_cmd_in(cgroup.as_ref());
+        // On timeout child is dropped & hence killed
+        let output = with_timeout(cmd.spawn()?.wait_with_output(), self.config.get_timeout())
+            .await
+            .and_then(|output| output);
+        if let Some(cgroup) = cgroup {
+            if let Err(e) = cgroup.remove().await {
+                error!("Unable to remove cgroup: {}", e);
+            }
+        }
+        Ok(output?)
     }
 
-    fn get_stdout_reader(&mut self) -> Option<Lines<BufReader<ChildStdout>>> {
+    fn get_stdout_reader(&mut self) -> Option<OutputReader> {
         let out = self.child.as_mut().map(|c| c.stdout.take())??;
-        let reader = BufReader::new(out).lines();
+        let reader = BufReader::new(Box::new(out) as Box<dyn AsyncRead + Send + Unpin>);
         Some(reader)
     }
 
-    fn get_stderr_reader(&mut self) -> Option<Lines<BufReader<ChildStderr>>> {
+    fn get_stderr_reader(&mut self) -> Option<OutputReader> {
         let out = self.child.as_mut().map(|c| c.stderr.take())??;
-        let reader = BufReader::new(out).lines();
+        let reader = BufReader::new(Box::new(out) as Box<dyn AsyncRead + Send + Unpin>);
         Some(reader)
     }
 
@@ -105,12 +157,21 @@ impl super::Executor for NativeExecutor {
         self.config.cwd.clone().to_path_buf()
     }
 
+    fn get_process(&self) -> Option<String> {
+        self.child.as_ref().map(|c| c.id().to_string())
+    }
+
     async fn close(mut self: Box<Self>) -> Result<(), Box<dyn Error>> {
         if let Some(mut c) = self.child {
             c.kill()?;
             let output = c.wait_with_output().await?;
             debug!("Driver exited with status: {}", output.status);
         }
+        if let Some(cgroup) = self.cgroup {
+            if let Err(e) = cgroup.remove().await {
+                error!("Unable to remove cgroup: {}", e);
+            }
+        }
         Ok(())
     }
 }
@@ -122,12 +183,73 @@ impl NativeExecutor {
             config,
             child: None,
             worker_task_id,
+            sandbox: false,
+            cgroup: None,
+        }
+    }
+
+    /// Native execution, but without any of our privileges or network access. Whole root is
+    /// read-only except cwd & a private /tmp, cwd stays owned by us as uid is mapped to namespace's
+    /// root, though target runs without any capabilities.
+    pub fn new_sandboxed(config: ExecutorConfig, worker_task_id: Option<i32>) -> Self {
+        let mut executor = Self::new(config, worker_task_id);
+        executor.sandbox = true;
+        executor
+    }
+
+    async fn create_cgroup(&self) -> Result<Option<Cgroup>, Box<dyn Error>> {
+        if let Some(limits) = self.config.limits.as_ref() {
+            let name = format!("{}-{}", self.worker_task_id.unwrap_or(0), Uuid::new_v4());
+            Ok(Some(Cgroup::create(&name, limits).await?))
+        } else {
+            Ok(None)
         }
     }
 
     fn create_cmd(&self) -> Command {
-        let mut cmd = Command::new(self.config.executable.clone());
-        cmd.args(self.config.args.clone())
+        self.create_cmd_in(self.cgroup.as_ref())
+    }
+
+    fn create_cmd_in(&self, cgroup: Option<&Cgroup>) -> Command {
+        // Wrappers are chained, each exec'ing into the next so that final pid is the target's
+        let mut argv: Vec<String> = Vec::new();
+        if let Some(cpus) = self.config.get_cpuset_list() {
+            argv.push("taskset".to_owned());
+            argv.push("-c".to_owned());
+            argv.push(cpus);
+        }
+        if let Some(cgroup) = cgroup {
+            argv.push("/bin/sh".to_owned());
+            argv.push("-c".to_owned());
+            argv.push(CGROUP_JOIN_SCRIPT.to_owned());
+            argv.push("sh".to_owned());
+            argv.push(cgroup.get_procs_path().to_string_lossy().into_owned());
+        }
+        if self.sandbox {
+            for arg in &[
+                "unshare",
+                "--user",
+                "--map-root-user",
+                "--mount",
+                "--net",
+                "--pid",
+                "--fork",
+                "--mount-proc",
+                "--kill-child",
+                "--",
+                "/bin/sh",
+                "-c",
+                SANDBOX_SCRIPT,
+                "sh",
+            ] {
+                argv.push(arg.to_string());
+            }
+        }
+        argv.push(self.config.executable.clone());
+
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..])
+            .args(self.config.args.clone())
             .envs(self.config.envs.clone())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
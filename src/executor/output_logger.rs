@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, error, warn};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use super::{Executor, OutputReader};
+use crate::common::intervals::WORKER_LOGS_UPLOAD_INTERVAL;
+use crate::common::worker_tasks::upload_worker_task_logs;
+use crate::common::xpc::get_orchestrator_client;
+use crate::models::NewWorkerTaskLog;
+
+// Log file is rotated once it crosses this size, with only a few older ones kept around
+const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const LOG_FILE_BACKUPS: usize = 2;
+// Lines are shipped to master once these many pile up, or every upload interval
+const LOG_UPLOAD_BATCH_SIZE: usize = 100;
+// Batches waiting to be shipped, newer ones are dropped if master can't keep up
+const LOG_UPLOAD_QUEUE_SIZE: usize = 10;
+
+pub const STDOUT_LOG: &str = "stdout";
+pub const STDERR_LOG: &str = "stderr";
+
+/// Drains stdout & stderr of a freshly spawned process, so that pipes never fill up. Output is
+/// kept in fuzzy_<stream>.log files in cwd & shipped to master when part of a worker task.
+pub fn spawn_output_loggers(executor: &mut Box<dyn Executor>, worker_task_id: Option<i32>) {
+    let cwd = executor.get_cwd_path();
+    for (stream, reader) in vec![
+        (STDOUT_LOG, executor.get_stdout_reader()),
+        (STDERR_LOG, executor.get_stderr_reader()),
+    ] {
+        if let Some(reader) = reader {
+            let logger = OutputLogger::new(stream, &cwd, worker_task_id);
+            tokio::spawn(async move {
+                if let Err(e) = logger.capture(reader).await {
+                    error!("Capturing {} of process failed: {}", stream, e);
+                }
+            });
+        }
+    }
+}
+
+struct OutputLogger {
+    stream: &'static str,
+    path: PathBuf,
+    worker_task_id: Option<i32>,
+    pending: Vec<NewWorkerTaskLog>,
+}
+
+impl OutputLogger {
+    fn new(stream: &'static str, cwd: &Path, worker_task_id: Option<i32>) -> Self {
+        Self {
+            stream,
+            path: cwd.join(format!("fuzzy_{}.log", stream)),
+            worker_task_id,
+            pending: Vec::new(),
+        }
+    }
+
+    async fn capture(mut self, mut reader: OutputReader) -> Result<(), Box<dyn Error>> {
+        let mut file = self.open().await?;
+        let mut size = file.metadata().await?.len();
+        let mut interval = tokio::time::interval(WORKER_LOGS_UPLOAD_INTERVAL);
+
+        // Uploads happen on their own, so a slow master never blocks draining of pipes
+        let (mut uploads, queue) = mpsc::channel(LOG_UPLOAD_QUEUE_SIZE);
+        tokio::spawn(upload_logs(self.stream, queue));
+
+        // Output is read as raw bytes as fuzzers are free to print anything. Partial line stays in
+        // buffer if interval ticks meanwhile
+        let mut line = Vec::new();
+        loop {
+            tokio::select! {
+                read = reader.read_until(b'\n', &mut line) => {
+                    if read? == 0 && line.is_empty() {
+                        break;
+                    }
+
+                    if size > LOG_FILE_MAX_SIZE {
+                        self.rotate().await?;
+                        file = self.open().await?;
+                        size = 0;
+                    }
+                    file.write_all(&line).await?;
+                    size += line.len() as u64;
+
+                    self.push(decode_line(&line));
+                    line.clear();
+                    if self.pending.len() >= LOG_UPLOAD_BATCH_SIZE {
+                        self.queue_upload(&mut uploads);
+                    }
+                },
+                _ = interval.tick() => {
+                    self.queue_upload(&mut uploads);
+                },
+            }
+        }
+
+        debug!("{} of process closed", self.stream);
+        self.queue_upload(&mut uploads);
+        Ok(())
+    }
+
+    async fn open(&self) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(&self.path).await
+    }
+
+    // fuzzy_stdout.log -> fuzzy_stdout.log.1 -> fuzzy_stdout.log.2 & so on
+    async fn rotate(&self) -> std::io::Result<()> {
+        debug!("Rotating log file {:?}", self.path);
+        for i in (1..LOG_FILE_BACKUPS).rev() {
+            let older = self.get_backup_path(i);
+            if older.exists() {
+                fs::rename(&older, self.get_backup_path(i + 1)).await?;
+            }
+        }
+        fs::rename(&self.path, self.get_backup_path(1)).await
+    }
+
+    fn get_backup_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn push(&mut self, line: String) {
+        if let Some(worker_task_id) = self.worker_task_id {
+            self.pending.push(NewWorkerTaskLog {
+                worker_task_id,
+                stream: self.stream.to_owned(),
+                line,
+            });
+        }
+    }
+
+    // Lines that fail to ship are dropped, they are still present in log file
+    fn queue_upload(&mut self, uploads: &mut mpsc::Sender<Vec<NewWorkerTaskLog>>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let logs: Vec<NewWorkerTaskLog> = self.pending.drain(..).collect();
+        let count = logs.len();
+        if uploads.try_send(logs).is_err() {
+            warn!("Upload of {} is lagging, dropping {} lines", self.stream, count);
+        }
+    }
+}
+
+/// Ships queued batches till logger is done & queue is drained
+async fn upload_logs(stream: &'static str, mut queue: mpsc::Receiver<Vec<NewWorkerTaskLog>>) {
+    while let Some(logs) = queue.recv().await {
+        let count = logs.len();
+        let client = get_orchestrator_client().await.map_err(|e| e.to_string());
+        let result = match client {
+            Ok(mut client) => upload_worker_task_logs(logs, &mut client)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Unable to upload {} lines of {}: {}", count, stream, e);
+        }
+    }
+}
+
+/// Reads next line of output, None once stream is closed
+pub async fn read_line(reader: &mut OutputReader) -> std::io::Result<Option<String>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line).await? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(decode_line(&line)))
+}
+
+/// Line ending is dropped, invalid utf-8 is replaced & NULs are stripped as database doesn't take them
+fn decode_line(line: &[u8]) -> String {
+    let mut end = line.len();
+    if end > 0 && line[end - 1] == b'\n' {
+        end -= 1;
+        if end > 0 && line[end - 1] == b'\r' {
+            end -= 1;
+        }
+    }
+    String::from_utf8_lossy(&line[..end]).replace('\0', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_decode_line() {
+        assert_eq!(decode_line(b"plain\n"), "plain");
+        assert_eq!(decode_line(b"crlf\r\n"), "crlf");
+        assert_eq!(decode_line(b"no newline"), "no newline");
+        assert_eq!(decode_line(b"\n"), "");
+        assert_eq!(decode_line(b"nul\0byte\n"), "nulbyte");
+        assert_eq!(decode_line(b"bad \xff\xfe utf8\n"), "bad \u{fffd}\u{fffd} utf8");
+    }
+}
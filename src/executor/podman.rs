@@ -0,0 +1,290 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use tracing::{debug, error};
+use tokio::{
+    io::{AsyncRead, BufReader},
+    process::{Child, Command},
+    sync::broadcast,
+};
+
+use super::container::ContainerContext;
+use super::corpus_syncer::CorpusSyncer;
+use super::crash_syncer::CrashSyncer;
+use super::{with_timeout, ExecutorConfig, OutputReader, WORKER_LABEL};
+use crate::fuzz_driver::{CorpusConfig, CrashConfig};
+use crate::utils::err_output;
+
+/// Rootless variant of docker executor, no daemon is required & containers are never privileged.
+/// Everything but the podman cli invocations is shared with docker executor.
+pub struct PodmanExecutor {
+    context: ContainerContext,
+    // Follows logs of spawned container, its output is that of container
+    child: Option<Child>,
+}
+
+#[tonic::async_trait]
+impl super::Executor for PodmanExecutor {
+    async fn setup(&self) -> Result<(), Box<dyn Error>> {
+        debug!("Setting up podman execution environment");
+
+        // Force pull the image in a blocking fashion
+        force_pull_image(self.context.config.image.clone()).await?;
+
+        self.context.setup_cwd().await
+    }
+
+    async fn create_relative_dirp(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.context.create_relative_dirp(path).await
+    }
+
+    async fn rm_relative_dirp(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.context.rm_relative_dirp(path).await
+    }
+
+    async fn wait(&self, mut kill_switch: broadcast::Receiver<u8>) -> Result<(), Box<dyn Error>> {
+        let identifier = self.context.identifier.clone();
+        tokio::select! {
+            result = check_if_container_alive(identifier) => {
+                result?;
+            },
+            _ = kill_switch.recv() => {
+                debug!("Kill received for podman executor, hope the command dies");
+            },
+        };
+        Ok(())
+    }
+
+    async fn spawn(&mut self) -> Result<(), Box<dyn Error>> {
+        // Container of previous process stays around if respawning, which would conflict by name.
+        // Without -f podman refuses to remove it if it is still running, so fail then
+        let mut cmd = Command::new("podman");
+        cmd.arg("rm")
+            .arg("--ignore")
+            .arg(self.context.identifier.clone())
+            .kill_on_drop(true);
+        let output = cmd.output().await?;
+        if output.status.success() == false {
+            err_output(output);
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("Unable to remove stale container: {}", self.context.identifier),
+            )));
+        }
+
+        // Detached client only prints container id & exits, so output is followed through logs
+        let mut cmd = self.create_cmd(false).await?;
+        let output = cmd.output().await?;
+        if output.status.success() == false {
+            err_output(output);
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Unable to run container: {}", self.context.identifier),
+            )));
+        }
+
+        // Logs keep container's stdout & stderr apart, previous follower is killed on drop if respawning
+        let mut cmd = Command::new("podman");
+        cmd.arg("logs")
+            .arg("-f")
+            .arg(self.context.identifier.clone())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        self.child = Some(cmd.spawn()?);
+        Ok(())
+    }
+
+    async fn spawn_blocking(&mut self) -> Result<std::process::Output, Box<dyn Error>> {
+        let mut cmd = self.create_cmd(true).await?;
+        let child = cmd.spawn()?;
+        let output = with_timeout(child.wait_with_output(), self.context.config.get_timeout())
+            .await
+            .and_then(|output| output);
+
+        // Killing podman client doesn't stop the container
+        if output.is_err() {
+            remove_container(&self.context.identifier).await?;
+        }
+        Ok(output?)
+    }
+
+    fn get_stdout_reader(&mut self) -> Option<OutputReader> {
+        let out = self.child.as_mut().map(|c| c.stdout.take())??;
+        let reader = BufReader::new(Box::new(out) as Box<dyn AsyncRead + Send + Unpin>);
+        Some(reader)
+    }
+
+    fn get_stderr_reader(&mut self) -> Option<OutputReader> {
+        let out = self.child.as_mut().map(|c| c.stderr.take())??;
+        let reader = BufReader::new(Box::new(out) as Box<dyn AsyncRead + Send + Unpin>);
+        Some(reader)
+    }
+
+    fn get_corpus_syncer(&self, config: CorpusConfig) -> Result<CorpusSyncer, Box<dyn Error>> {
+        self.context.get_corpus_syncer(config)
+    }
+
+    fn get_crash_syncer(&self, config: CrashConfig) -> Result<CrashSyncer, Box<dyn Error>> {
+        self.context.get_crash_syncer(config)
+    }
+
+    fn get_cwd_path(&self) -> PathBuf {
+        self.context.mapped_cwd.clone()
+    }
+
+    // Container is named by identifier
+    fn get_process(&self) -> Option<String> {
+        Some(self.context.identifier.clone())
+    }
+
+    async fn close(mut self: Box<Self>) -> Result<(), Box<dyn Error>> {
+        // We are here means we need to stop now
+        let mut cmd = Command::new("podman");
+        cmd.arg("stop").arg(self.context.identifier.clone()).kill_on_drop(true);
+
+        let output = cmd.output().await?;
+        if output.status.success() == false {
+            error!("Unable to stop container: {}", self.context.identifier);
+            err_output(output);
+        }
+
+        // Remove the container
+        remove_container(&self.context.identifier).await?;
+        Ok(())
+    }
+}
+
+impl PodmanExecutor {
+    pub fn new(config: ExecutorConfig, worker_task_id: Option<i32>) -> Self {
+        debug!("Creating new podman executor with config: {:#?}", config);
+        Self {
+            context: ContainerContext::new(config, worker_task_id),
+            child: None,
+        }
+    }
+
+    async fn create_cmd(&self, blocking: bool) -> Result<Command, Box<dyn Error>> {
+        let config = &self.context.config;
+
+        let mut cmd = Command::new("podman");
+        cmd.arg("run")
+            // Map our uid into the container as is, so files in cwd stay accessible to us
+            .arg("--userns=keep-id")
+            .args(self.context.get_run_options(blocking).await?)
+            .arg(config.image.clone())
+            .args(config.args.clone())
+            // Podman takes values of env keys from launch environment
+            .envs(config.envs.clone())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .current_dir(self.context.mapped_cwd.as_path())
+            .kill_on_drop(true);
+        debug!("Command: {:#?}", cmd);
+        Ok(cmd)
+    }
+}
+
+/// Returns whether container got removed, failures are logged
+async fn remove_container(name: &str) -> Result<bool, Box<dyn Error>> {
+    let mut cmd = Command::new("podman");
+    cmd.arg("rm").arg("-f").arg(name).kill_on_drop(true);
+    let rm_output = cmd.output().await?;
+    if rm_output.status.success() == false {
+        error!("Unable to remove container: {}", name);
+        err_output(rm_output);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Rootless podman works without any daemon, so being able to get info is good enough
+pub async fn is_podman_usable() -> bool {
+    let mut cmd = Command::new("podman");
+    cmd.arg("info").stdout(Stdio::null()).stderr(Stdio::null()).kill_on_drop(true);
+    match cmd.output().await {
+        Ok(output) => output.status.success(),
+        Err(_) => false,
+    }
+}
+
+/// Removes containers labelled with worker uuid & returns names of ones actually removed
+pub async fn remove_worker_containers(worker_uuid: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut cmd = Command::new("podman");
+    cmd.arg("ps")
+        .arg("-a")
+        .arg(format!("--filter=label={}={}", WORKER_LABEL, worker_uuid))
+        .arg("--format={{.Names}}")
+        .kill_on_drop(true);
+    let output = cmd.output().await?;
+    if output.status.success() == false {
+        err_output(output);
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Unable to list podman containers of worker",
+        )));
+    }
+
+    let names: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|name| name.trim().to_owned())
+        .filter(|name| name.is_empty() == false)
+        .collect();
+    let mut removed = Vec::new();
+    for name in names {
+        debug!("Removing podman container {} of worker", name);
+        if remove_container(&name).await? {
+            removed.push(name);
+        }
+    }
+    Ok(removed)
+}
+
+async fn force_pull_image(image: String) -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::new("podman");
+    cmd.arg("pull")
+        .arg(image.clone())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let child = cmd.spawn()?;
+    let output = child.wait_with_output().await?;
+
+    if output.status.success() == false {
+        error!("Image pull exited with status: {:?}", output.status.code());
+        err_output(output);
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unable to pull image: {}", &image),
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+// This check should be very liberal
+async fn check_if_container_alive(identitifer: String) -> Result<(), Box<dyn Error>> {
+    let mut interval = tokio::time::interval(crate::common::intervals::WORKER_PROCESS_CHECK_INTERVAL);
+    let name_filter = format!("name={}", identitifer);
+    let mut fail_count = 0;
+    loop {
+        interval.tick().await;
+        let mut cmd = Command::new("podman");
+        cmd.arg("ps")
+            .arg("-f")
+            .arg(name_filter.as_str())
+            .arg("--format={{.ID}}")
+            .kill_on_drop(true);
+        let output = cmd.output().await?;
+        if output.stdout.len() == 0 {
+            if fail_count > 4 {
+                break;
+            } else {
+                fail_count = fail_count + 1;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use tracing::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::FuzzConfig;
+use crate::executor::{self, ExecutorEnum};
+use crate::utils::fs::{mkdir_p, rm_r};
+
+// Relative to worker cwd, like worker metadata
+const TASK_RECORD_DIR: &str = ".fuzzy_tasks";
+const TASK_RECORD_EXT: &str = "yaml";
+
+/// Profile a worker task was started with, kept on disk till the task is torn down. If worker dies
+/// in between, it is all that is needed to find the task's cwd & syncers again after restart
+#[derive(Serialize, Deserialize, Debug)]
+struct TaskRecord {
+    worker_task_id: i32,
+    config: FuzzConfig,
+}
+
+fn get_record_path(record_dir: &Path, worker_task_id: i32) -> PathBuf {
+    record_dir.join(format!("{}.{}", worker_task_id, TASK_RECORD_EXT))
+}
+
+async fn write_record(record_dir: &Path, worker_task_id: i32, config: &FuzzConfig) -> Result<(), Box<dyn Error>> {
+    mkdir_p(record_dir).await?;
+    let record = TaskRecord {
+        worker_task_id,
+        config: config.clone(),
+    };
+    let record_path = get_record_path(record_dir, worker_task_id);
+    fs::write(record_path, serde_yaml::to_vec(&record)?).await?;
+    Ok(())
+}
+
+async fn read_records(record_dir: &Path) -> Result<Vec<(PathBuf, TaskRecord)>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    if record_dir.is_dir() == false {
+        return Ok(records);
+    }
+    let mut dir = fs::read_dir(record_dir).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+        if path.extension().map(|e| e == TASK_RECORD_EXT).unwrap_or(false) {
+            match serde_yaml::from_slice(&fs::read(&path).await?) {
+                Ok(record) => records.push((path, record)),
+                Err(e) => error!("Unable to read task record {:?}: {}", path, e),
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Called before cwd of a worker task is set up, tasks not run by a worker have nothing to record
+pub async fn record_task(worker_task_id: Option<i32>, config: &FuzzConfig) -> Result<(), Box<dyn Error>> {
+    match worker_task_id {
+        Some(id) => write_record(Path::new(TASK_RECORD_DIR), id, config).await,
+        None => Ok(()),
+    }
+}
+
+/// Called once task is torn down, nothing is left behind to salvage then
+pub async fn forget_task(worker_task_id: Option<i32>) -> Result<(), Box<dyn Error>> {
+    if let Some(id) = worker_task_id {
+        let record_path = get_record_path(Path::new(TASK_RECORD_DIR), id);
+        if record_path.exists() {
+            fs::remove_file(record_path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Tears down worker tasks left behind by a dead worker, like fuzz driver would have. Their process is
+/// stopped, crashes & corpus still on disk are uploaded and then cwd is cleaned up. Records of tasks
+/// that fail to be salvaged are kept, so that their files are not lost & salvage is tried again on
+/// next start
+pub async fn salvage_tasks() -> Result<usize, Box<dyn Error>> {
+    let mut salvaged = 0;
+    for (record_path, record) in read_records(Path::new(TASK_RECORD_DIR)).await? {
+        info!(
+            "Salvaging worker task {} left behind by earlier run",
+            record.worker_task_id
+        );
+        if let Err(e) = salvage_task(record).await {
+            error!("Unable to salvage task from {:?}: {}", record_path, e);
+            continue;
+        }
+        fs::remove_file(record_path).await?;
+        salvaged += 1;
+    }
+    Ok(salvaged)
+}
+
+async fn salvage_task(record: TaskRecord) -> Result<(), Box<dyn Error>> {
+    let TaskRecord { worker_task_id, config } = record;
+
+    // Executors are named after their config, so this is the same cwd & container the task had
+    let runner = executor::new(config.execution.clone(), Some(worker_task_id));
+    let cwd = runner.get_cwd_path();
+    let mut corpus_syncer = runner.get_corpus_syncer(config.corpus.clone())?;
+    corpus_syncer.set_driver(format!("{:?}", config.driver));
+    let crash_syncer = runner.get_crash_syncer(config.crash.clone())?;
+
+    // Stop container if it is still around, so nothing is written while uploading
+    runner.close().await?;
+    if cwd.is_dir() == false {
+        debug!("Cwd {:?} of worker task {} is already gone", cwd, worker_task_id);
+        return Ok(());
+    }
+
+    // Crash directory might be cwd itself, so only uploaded crashes are removed
+    if cwd.join(&config.crash.path).is_dir() {
+        crash_syncer.upload_existing_crashes().await?;
+    }
+    crash_syncer.close().await?;
+    if cwd.join(&config.corpus.path).is_dir() {
+        corpus_syncer.close(UNIX_EPOCH).await?;
+        rm_r(&cwd.join(&config.corpus.path)).await?;
+    }
+
+    // Container cwds belong to the task alone, unlike native ones that are given by profile
+    match config.execution.executor {
+        ExecutorEnum::Docker | ExecutorEnum::Podman => rm_r(&cwd).await?,
+        ExecutorEnum::Native | ExecutorEnum::Sandbox => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use uuid::Uuid;
+    #[test]
+    fn test_task_records() {
+        let record_dir = env::temp_dir().join(format!("fuzzy_tasks_{}", Uuid::new_v4()));
+        let config: FuzzConfig = serde_yaml::from_str(
+            r#"
+driver: Libfuzzer
+execution:
+  executor: Native
+  cpus: 1
+  executable: /fuzzer
+  cwd: /fuzzing
+corpus:
+  path: corpus
+  label: test
+  refresh_interval: 10
+  upload: true
+  upload_filter: ".*"
+crash:
+  path: crashes
+  label: test
+  filter: "^crash-"
+"#,
+        )
+        .unwrap();
+
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let records = rt.block_on(async {
+            write_record(&record_dir, 7, &config).await.unwrap();
+            // Only records are picked up
+            fs::write(record_dir.join("7.yaml.partial"), b"").await.unwrap();
+            read_records(&record_dir).await.unwrap()
+        });
+
+        assert_eq!(records.len(), 1);
+        let (record_path, record) = &records[0];
+        assert_eq!(record_path, &get_record_path(&record_dir, 7));
+        assert_eq!(record.worker_task_id, 7);
+        assert_eq!(record.config.crash.filter.as_str(), "^crash-");
+        assert_eq!(record.config.corpus.path, config.corpus.path);
+
+        std::fs::remove_dir_all(&record_dir).unwrap();
+    }
+}
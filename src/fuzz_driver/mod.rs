@@ -1,19 +1,22 @@
+use std::collections::VecDeque;
 use std::error::Error;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use tracing::{debug, error, info, warn};
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{broadcast, oneshot};
+use tokio::sync::{broadcast, oneshot, watch};
 use validator::Validate;
 
-use super::executor::{self, Executor, ExecutorConfig};
+use super::executor::{self, output_logger::spawn_output_loggers, Executor, ExecutorConfig};
 use crate::common::profiles::{validate_fuzz_profile, validate_relative_path};
-use crate::common::worker_tasks::{mark_worker_task_active, mark_worker_task_inactive};
+use crate::common::worker_tasks::{mark_worker_task_active, mark_worker_task_inactive, update_worker_task_restarts};
 use stats::{FuzzStatCollector, FuzzStatConfig};
 
 mod honggfuzz;
+pub mod leftovers;
 mod libfuzzer;
 pub mod stats;
 
@@ -32,6 +35,111 @@ pub struct FuzzConfig {
     pub corpus: CorpusConfig,
     pub crash: CrashConfig,
     pub fuzz_stat: Option<FuzzStatConfig>,
+
+    #[serde(default)]
+    pub restart: Option<RestartPolicy>,
+
+    #[serde(default)]
+    pub requirements: Requirements,
+
+    // Name of build variant that is fuzzed, its execution is used in place of the one above
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+impl FuzzConfig {
+    /// Swaps in execution of picked build variant, profile is expected to be validated already
+    pub fn apply_variant(&mut self) {
+        let name = match self.variant.as_ref() {
+            Some(name) => name,
+            None => return,
+        };
+        let execution = self
+            .crash
+            .variants
+            .iter()
+            .find(|v| &v.name == name)
+            .and_then(|v| v.execution.clone());
+        if let Some(execution) = execution {
+            self.execution = execution;
+        }
+    }
+
+    /// Every executor a task might spawn, fuzzer itself & ones used for crashes and stats
+    pub fn get_executor_configs(&self) -> Vec<&ExecutorConfig> {
+        let mut configs = vec![&self.execution];
+        configs.extend(self.crash.validate.iter());
+        configs.extend(self.crash.deduplicate.iter());
+        configs.extend(self.crash.variants.iter().map(|v| &v.validate));
+        configs.extend(self.fuzz_stat.iter().map(|s| &s.execution));
+        configs
+    }
+}
+
+/// Worker capabilities needed apart from executors, scheduler only places task on workers having them
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Requirements {
+    // Like x86_64 or aarch64
+    #[serde(default)]
+    pub arch: Option<String>,
+
+    #[serde(default)]
+    pub kvm: bool,
+
+    // In MB, on volume map of worker
+    #[serde(default)]
+    pub disk_free: Option<i32>,
+
+    // Standalone fuzzer binaries needed in PATH of worker
+    #[serde(default)]
+    pub fuzzers: Vec<String>,
+
+    // Labels given to workers through their config, like a team or a network zone
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+/// What driver is actually running, published for worker's heartbeats
+#[derive(Debug, Clone, Default)]
+pub struct DriverState {
+    pub process: Option<String>,
+    pub restarts: i32,
+}
+
+/// Fuzzer process is respawned in place when it dies, till it dies too often
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RestartPolicy {
+    // Restarts allowed within window, task is torn down on exceeding
+    pub max_restarts: usize,
+
+    // Seconds to wait before restarting, doubled for every restart already in window
+    pub backoff: u64,
+
+    // Seconds over which restarts are counted
+    pub window: u64,
+}
+
+impl RestartPolicy {
+    pub fn get_window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+
+    pub fn get_backoff(&self, recent_restarts: usize) -> Duration {
+        Duration::from_secs(self.backoff.saturating_mul(1u64 << recent_restarts.min(16)))
+    }
+
+    /// Forgets restarts that are out of window as of now & returns how long to wait before next
+    /// one, none if too many restarts happened within window
+    pub fn next_backoff(&self, restarts: &mut VecDeque<Instant>, now: Instant) -> Option<Duration> {
+        while restarts.front().map(|t| now.duration_since(*t) > self.get_window()) == Some(true) {
+            restarts.pop_front();
+        }
+        if restarts.len() >= self.max_restarts {
+            None
+        } else {
+            Some(self.get_backoff(restarts.len()))
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Validate, Debug, Clone)]
@@ -50,6 +158,20 @@ pub struct CrashConfig {
 
     #[serde(default)]
     pub deduplicate: Option<ExecutorConfig>,
+
+    // Other builds of same target, like asan or ubsan, that every crash is validated against
+    #[serde(default)]
+    pub variants: Vec<BuildVariant>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildVariant {
+    pub name: String,
+    pub validate: ExecutorConfig,
+
+    // Fuzzer built as this variant, needed only if profile fuzzes this variant
+    #[serde(default)]
+    pub execution: Option<ExecutorConfig>,
 }
 
 #[derive(Serialize, Deserialize, Validate, Debug, Clone)]
@@ -60,6 +182,10 @@ pub struct CorpusConfig {
     pub refresh_interval: u64,
     pub upload: bool,
 
+    // Read only labels, only downloaded once during setup
+    #[serde(default)]
+    pub seed_labels: Vec<String>,
+
     #[serde(with = "serde_regex")]
     pub upload_filter: Regex,
 }
@@ -102,12 +228,10 @@ pub trait FuzzDriver: Send {
         Ok(())
     }
 
-    // Create corpus and crash dir
+    // Remove corpus dir, crashes are removed by crash syncer as crash dir might be cwd itself
     async fn teardown(&mut self, executor: &Box<dyn Executor>) -> Result<(), Box<dyn Error>> {
         let config = self.get_fuzz_config();
         executor.rm_relative_dirp(&config.corpus.path).await?;
-        // TODO: Donot delete crashes dir as of now
-        // executor.create_relative_dirp(&config.crash.path);
         Ok(())
     }
 
@@ -115,6 +239,7 @@ pub trait FuzzDriver: Send {
         &mut self,
         kill_switch: oneshot::Receiver<u8>,
         death_switch: oneshot::Sender<u8>,
+        state: watch::Sender<DriverState>,
     ) -> Result<(), Box<dyn Error>> {
         // Before anything call fix args, so that drivers can do changes
         self.fix_args();
@@ -125,6 +250,9 @@ pub trait FuzzDriver: Send {
 
         info!("Starting generic fuzz driver for {:#?}", worker_task_id);
 
+        // Whatever is set up from here on is salvaged by worker after a restart, till task is torn down
+        leftovers::record_task(worker_task_id, &config).await?;
+
         // Setup runner, corpus syncer, crash syncer, stat collector
         let mut runner = executor::new(config.execution.clone(), worker_task_id);
         runner.setup().await?;
@@ -132,6 +260,7 @@ pub trait FuzzDriver: Send {
 
         // Spawn off corpus sync
         let mut corpus_syncer = runner.get_corpus_syncer(config.corpus.clone())?;
+        corpus_syncer.set_driver(format!("{:?}", config.driver));
         corpus_syncer.setup_corpus().await?;
 
         // Spawn off crash sync
@@ -142,14 +271,24 @@ pub trait FuzzDriver: Send {
 
         // Start the actual process
         runner.spawn().await?;
-
-        // Mark as task active
-        mark_worker_task_active(worker_task_id).await?;
+        spawn_output_loggers(&mut runner, worker_task_id);
+        // Nobody might be listening, like when run from cli
+        let _ = state.broadcast(DriverState {
+            process: runner.get_process(),
+            restarts: 0,
+        });
+
+        // Mark as task active. From here on runner has to be closed on every error, as a detached
+        // container would outlive task & block it from being spawned again
+        let marked = mark_worker_task_active(worker_task_id).await.map_err(|e| e.to_string());
+        if let Err(e) = marked {
+            runner.close().await?;
+            return Err(e.into());
+        }
         // Listen and wait for all and kill switch
         let (longshot, longshot_recv) = broadcast::channel(5);
         let crash_longshot_recv = longshot.subscribe();
         let stat_longshot_recv = longshot.subscribe();
-        let runner_longshot_recv = longshot.subscribe();
         tokio::select! {
             result = corpus_syncer.sync_corpus(longshot_recv) => {
                 error!("Error in syncing corpus: {:?}", result);
@@ -164,8 +303,8 @@ pub trait FuzzDriver: Send {
             _ = kill_switch => {
                 warn!("Received kill for lib fuzzer driver");
             },
-            result = runner.wait(runner_longshot_recv) => {
-                error!("Error in executor: {:?}", result);
+            result = supervise(&mut runner, config.restart.clone(), worker_task_id, &longshot, &state) => {
+                error!("Fuzzer process exited for good: {:?}", result);
             },
         }
         let close_time = std::time::SystemTime::now();
@@ -180,16 +319,73 @@ pub trait FuzzDriver: Send {
         info!("Sending kill signal for execturo {:?} as select! ended", worker_task_id);
 
         // Sync corpus first and then close the executor
-        // Exactly reverse order of how things were created
-        corpus_syncer.close(close_time).await?;
-        self.teardown(&runner).await?;
+        // Exactly reverse order of how things were created, runner is closed even if syncing fails
+        let mut closed = corpus_syncer.close(close_time).await.map_err(|e| e.to_string());
+        if closed.is_ok() {
+            closed = crash_syncer.close().await.map_err(|e| e.to_string());
+        }
+        if closed.is_ok() {
+            closed = self.teardown(&runner).await.map_err(|e| e.to_string());
+        }
         runner.close().await?;
+        closed?;
+        leftovers::forget_task(worker_task_id).await?;
 
         mark_worker_task_inactive(worker_task_id).await?;
         Ok(())
     }
 }
 
+/// Waits on fuzzer process & respawns it as per restart policy, returns once it gives up
+async fn supervise(
+    runner: &mut Box<dyn Executor>,
+    policy: Option<RestartPolicy>,
+    worker_task_id: Option<i32>,
+    longshot: &broadcast::Sender<u8>,
+    state: &watch::Sender<DriverState>,
+) -> Result<(), Box<dyn Error>> {
+    let mut restarts: VecDeque<Instant> = VecDeque::new();
+    let mut total_restarts = 0;
+    loop {
+        if let Err(e) = runner.wait(longshot.subscribe()).await {
+            error!("Error in executor: {:?}", e);
+        }
+        let policy = match policy.as_ref() {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+
+        let backoff = match policy.next_backoff(&mut restarts, Instant::now()) {
+            Some(backoff) => backoff,
+            None => {
+                error!(
+                    "Fuzzer restarted {} times within {:?}, giving up",
+                    restarts.len(),
+                    policy.get_window()
+                );
+                return Ok(());
+            }
+        };
+        warn!(
+            "Fuzzer process for {:?} exited, restarting in {:?}",
+            worker_task_id, backoff
+        );
+        tokio::time::delay_for(backoff).await;
+        runner.spawn().await?;
+        spawn_output_loggers(runner, worker_task_id);
+
+        restarts.push_back(Instant::now());
+        total_restarts += 1;
+        let _ = state.broadcast(DriverState {
+            process: runner.get_process(),
+            restarts: total_restarts,
+        });
+        if let Err(e) = update_worker_task_restarts(worker_task_id, total_restarts).await {
+            error!("Unable to report restarts to master: {}", e);
+        }
+    }
+}
+
 pub struct FuzzyDriver {
     config: FuzzConfig,
     worker_task_id: Option<i32>,
@@ -241,3 +437,38 @@ pub fn new(config: FuzzConfig, worker_task_id: Option<i32>) -> Box<dyn FuzzDrive
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_restart_policy() {
+        let policy = RestartPolicy {
+            max_restarts: 2,
+            backoff: 5,
+            window: 60,
+        };
+        let start = Instant::now();
+        let mut restarts = VecDeque::new();
+
+        assert_eq!(policy.next_backoff(&mut restarts, start), Some(Duration::from_secs(5)));
+        restarts.push_back(start);
+        // Backoff doubles for every restart within window
+        assert_eq!(
+            policy.next_backoff(&mut restarts, start + Duration::from_secs(10)),
+            Some(Duration::from_secs(10))
+        );
+        restarts.push_back(start + Duration::from_secs(10));
+        assert_eq!(
+            policy.next_backoff(&mut restarts, start + Duration::from_secs(20)),
+            None
+        );
+
+        // First restart falls out of window, so one more is allowed
+        assert_eq!(
+            policy.next_backoff(&mut restarts, start + Duration::from_secs(61)),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(restarts.len(), 1);
+    }
+}
@@ -1,20 +1,28 @@
+use std::collections::HashSet;
 use std::error::Error;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use lcov_parser::{FromFile, LCOVRecord};
 use tracing::{debug, error};
-use tokio::fs::read_dir;
+use tokio::fs::{self, read_dir};
 use tokio::stream::StreamExt;
+use tokio::sync::Mutex;
+use tonic::transport::channel::Channel;
 
 use super::{FuzzStatCollector, FuzzStatConfig};
-use crate::common::corpora::download_corpus_to_disk;
+use crate::common::corpora::{download_corpus, get_corpus_file_path, update_corpus_coverage, write_corpus_to_disk};
 use crate::common::xpc::get_orchestrator_client;
-use crate::executor;
+use crate::executor::{self, Executor};
 use crate::fuzz_driver::FuzzConfig;
-use crate::models::NewFuzzStat;
+use crate::models::{Corpus, NewFuzzStat};
 use crate::utils::err_output;
-use crate::utils::fs::rm_r;
+use crate::utils::fs::{mkdir_p, rm_r};
+use crate::xpc::orchestrator_client::OrchestratorClient;
+
+// Lines hit so far by corpus of a worker task are kept here, so that they survive restarts
+const COVERAGE_BASELINE_DIR: &str = ".fuzzy_coverage";
 
 #[derive(Clone)]
 pub struct LCovCollector {
@@ -23,6 +31,8 @@ pub struct LCovCollector {
     corpus_label: String,
     last_sync: SystemTime,
     refresh_interval: Duration,
+    // Source lines (file:line) hit by corpus measured so far, loaded on first use
+    baseline: Arc<Mutex<Option<HashSet<String>>>>,
 }
 
 impl LCovCollector {
@@ -33,6 +43,7 @@ impl LCovCollector {
             corpus_label: full_config.corpus.label,
             last_sync: UNIX_EPOCH,
             refresh_interval: Duration::from_secs(full_config.corpus.refresh_interval),
+            baseline: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -47,73 +58,182 @@ impl FuzzStatCollector for LCovCollector {
         debug!("Getting new stat using lcov collector");
         let mut client = get_orchestrator_client().await?;
 
-        // Create an executor
-        let mut executor = executor::new(self.config.execution.clone(), self.worker_task_id);
-
-        // Get latest corpus to cwd
-        executor.setup().await?;
-
         // Download latest corpus found by this worker
-        let cwd = executor.get_cwd_path();
-        let num_files = download_corpus_to_disk(
+        let corpora = download_corpus(
             self.corpus_label.clone(),
             None,
             self.worker_task_id,
             Some(10), // Get 10 latest samples
             self.last_sync,
-            cwd.as_path(),
             &mut client,
         )
         .await?;
+        if corpora.len() == 0 {
+            debug!("No corpus could be downloaded, doing nothing");
+            return Ok(None);
+        }
+        debug!("{} corpus downloaded for stat collection", corpora.len());
 
-        let mut new_fuzz_stat: Option<NewFuzzStat> = None;
-        if num_files > 0 {
-            debug!("{} corpus downloaded for stat collection", num_files);
+        // Create an executor, cwd is reused for every measurement
+        let mut executor = executor::new(self.config.execution.clone(), self.worker_task_id);
+        executor.setup().await?;
 
-            let output = executor.spawn_blocking().await?;
-            if output.status.success() == false {
-                error!("Stat collection execution failed");
-                err_output(output);
-            }
-            // TODO: Not necessary but let us keep it for now so that if we add volume cleanup it
-            // should be done.
-            // executor.close().await?;
-
-            // We look for a .lcov file anyway
-            let entries = read_dir(cwd.as_path()).await?;
-            let lcov_files = entries.filter_map(|f| {
-                if let Ok(file) = f {
-                    let path = file.path();
-                    let extension = path.extension();
-                    if extension.is_some() && extension.unwrap() == "lcov" {
-                        return Some(path);
+        let mut baseline = self.baseline.lock().await;
+        if baseline.is_none() {
+            *baseline = Some(self.load_baseline(&mut executor, &mut client).await?);
+        }
+        let baseline = baseline.as_mut().unwrap();
+
+        let new_fuzz_stat = match self.measure(&mut executor, &corpora).await? {
+            Some((stat, _)) => Some(stat),
+            None => None,
+        };
+
+        // Every corpus not measured yet is run on its own, so that it is credited only with lines
+        // nobody hit before it
+        let mut measured = 0;
+        for corpus in corpora.iter().filter(|c| c.coverage.is_none()) {
+            let result = self
+                .measure(&mut executor, std::slice::from_ref(corpus))
+                .await
+                .map_err(|e| e.to_string());
+            match result {
+                Ok(Some((_, lines))) => {
+                    let coverage = add_to_baseline(baseline, lines);
+                    if let Err(e) = update_corpus_coverage(vec![corpus.id], coverage, &mut client).await {
+                        error!("Failed to record coverage of corpus {}: {}", corpus.id, e);
                     }
+                    measured += 1;
                 }
-                None
-            });
-            let mut lcov_paths: Vec<PathBuf> = lcov_files.collect::<Vec<PathBuf>>().await;
-
-            // TODO: Ugliest piece of code I ever wrote, fix this
-            if let Some(lcov_path) = lcov_paths.pop() {
-                let result = self.parse_lcov(&lcov_path);
-                if let Ok(stat) = result {
-                    new_fuzz_stat = Some(stat);
-                } else {
-                    error!("Failed to parse merged lcov: {:?}", result);
-                }
-            } else {
-                error!("No .lcov file found, so exiting");
+                Ok(None) => {}
+                Err(e) => error!("Failed to measure coverage of corpus {}: {}", corpus.id, e),
+            }
+        }
+        if measured > 0 {
+            if let Err(e) = self.save_baseline(baseline).await {
+                error!("Failed to save coverage baseline: {}", e);
             }
-        } else {
-            debug!("No corpus could be downloaded, doing nothing");
         }
 
-        rm_r(&cwd).await?;
+        rm_r(&executor.get_cwd_path()).await?;
         Ok(new_fuzz_stat)
     }
 }
 
 impl LCovCollector {
+    fn get_baseline_path(&self) -> Option<PathBuf> {
+        self.worker_task_id
+            .map(|id| Path::new(COVERAGE_BASELINE_DIR).join(format!("{}.baseline", id)))
+    }
+
+    /// Baseline is read from disk, if it is lost then corpus measured earlier is run together to rebuild
+    /// it, without crediting anyone
+    async fn load_baseline(
+        &self,
+        executor: &mut Box<dyn Executor>,
+        client: &mut OrchestratorClient<Channel>,
+    ) -> Result<HashSet<String>, Box<dyn Error>> {
+        let path = match self.get_baseline_path() {
+            Some(path) => path,
+            None => return Ok(HashSet::new()),
+        };
+        if path.is_file() {
+            let content = fs::read_to_string(&path).await?;
+            return Ok(content.lines().map(|line| line.to_owned()).collect());
+        }
+
+        debug!("No coverage baseline at {:?}, rebuilding it from measured corpus", path);
+        let measured: Vec<Corpus> = download_corpus(
+            self.corpus_label.clone(),
+            None,
+            self.worker_task_id,
+            None,
+            UNIX_EPOCH,
+            client,
+        )
+        .await?
+        .into_iter()
+        .filter(|c| c.coverage.is_some())
+        .collect();
+        if measured.len() == 0 {
+            return Ok(HashSet::new());
+        }
+        let baseline = match self.measure(executor, &measured).await? {
+            Some((_, lines)) => lines,
+            None => HashSet::new(),
+        };
+        self.save_baseline(&baseline).await?;
+        Ok(baseline)
+    }
+
+    async fn save_baseline(&self, baseline: &HashSet<String>) -> Result<(), Box<dyn Error>> {
+        if let Some(path) = self.get_baseline_path() {
+            mkdir_p(Path::new(COVERAGE_BASELINE_DIR)).await?;
+            let mut lines: Vec<&String> = baseline.iter().collect();
+            lines.sort();
+            let content: Vec<&str> = lines.iter().map(|line| line.as_str()).collect();
+
+            // Write & rename, so that a crash midway never leaves a truncated baseline behind
+            let partial_path = path.with_extension("partial");
+            fs::write(&partial_path, content.join("\n")).await?;
+            fs::rename(&partial_path, &path).await?;
+        }
+        Ok(())
+    }
+
+    /// Runs coverage executor over given corpus only & returns stat along with lines hit
+    async fn measure(
+        &self,
+        executor: &mut Box<dyn Executor>,
+        corpora: &[Corpus],
+    ) -> Result<Option<(NewFuzzStat, HashSet<String>)>, Box<dyn Error>> {
+        let cwd = executor.get_cwd_path();
+        write_corpus_to_disk(&corpora.to_vec(), cwd.as_path()).await?;
+
+        let output = executor.spawn_blocking().await?;
+        if output.status.success() == false {
+            error!("Stat collection execution failed");
+            err_output(output);
+        }
+
+        // We look for a .lcov file anyway
+        let entries = read_dir(cwd.as_path()).await?;
+        let lcov_files = entries.filter_map(|f| {
+            if let Ok(file) = f {
+                let path = file.path();
+                let extension = path.extension();
+                if extension.is_some() && extension.unwrap() == "lcov" {
+                    return Some(path);
+                }
+            }
+            None
+        });
+        let mut lcov_paths: Vec<PathBuf> = lcov_files.collect::<Vec<PathBuf>>().await;
+
+        let measured = match lcov_paths.last() {
+            Some(lcov_path) => match (self.parse_lcov(lcov_path), parse_lcov_lines(lcov_path)) {
+                (Ok(stat), Ok(lines)) => Some((stat, lines)),
+                (stat, lines) => {
+                    error!("Failed to parse lcov: {:?} {:?}", stat.err(), lines.err());
+                    None
+                }
+            },
+            None => {
+                error!("No .lcov file found");
+                None
+            }
+        };
+
+        // Leave cwd clean for next measurement
+        for lcov_path in lcov_paths.drain(..) {
+            fs::remove_file(lcov_path).await?;
+        }
+        for corpus in corpora.iter() {
+            fs::remove_file(get_corpus_file_path(&cwd, &corpus.checksum)).await?;
+        }
+        Ok(measured)
+    }
+
     fn parse_lcov(&self, path: &Path) -> Result<NewFuzzStat, Box<dyn Error>> {
         // https://docs.rs/lcov-parser/3.2.2/src/lcov_parser/record.rs.html#18
         // As of now we only deal with aggregates LinesHit, LinesFound, BranchesHit, BranchesFound
@@ -167,3 +287,61 @@ impl LCovCollector {
         }
     }
 }
+
+/// Source lines (file:line) with a non zero hit count
+fn parse_lcov_lines(path: &Path) -> Result<HashSet<String>, Box<dyn Error>> {
+    let mut parser = lcov_parser::LCOVParser::from_file(path)?;
+    let records = parser.parse()?;
+
+    let mut lines = HashSet::new();
+    let mut source_file = String::new();
+    for record in records.iter() {
+        match record {
+            LCOVRecord::SourceFile(file) => source_file = file.clone(),
+            LCOVRecord::Data(data) if data.count > 0 => {
+                lines.insert(format!("{}:{}", source_file, data.line));
+            }
+            _ => continue,
+        }
+    }
+    Ok(lines)
+}
+
+/// Adds lines to baseline & returns how many of them weren't in it
+fn add_to_baseline(baseline: &mut HashSet<String>, lines: HashSet<String>) -> i32 {
+    let mut added = 0;
+    for line in lines.into_iter() {
+        if baseline.insert(line) {
+            added += 1;
+        }
+    }
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_coverage_baseline() {
+        let lcov = "TN:\nSF:/src/a.c\nDA:1,1\nDA:2,0\nDA:3,4\nend_of_record\nSF:/src/b.c\nDA:1,2\nend_of_record\n";
+        let path = std::env::temp_dir().join(format!("{}.lcov", uuid::Uuid::new_v4()));
+        std::fs::write(&path, lcov).unwrap();
+        let lines = parse_lcov_lines(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let expected: HashSet<String> = vec!["/src/a.c:1", "/src/a.c:3", "/src/b.c:1"]
+            .into_iter()
+            .map(|l| l.to_owned())
+            .collect();
+        assert_eq!(lines, expected);
+
+        // Each entry is only credited with lines nobody hit before it
+        let mut baseline = HashSet::new();
+        assert_eq!(add_to_baseline(&mut baseline, lines.clone()), 3);
+        assert_eq!(add_to_baseline(&mut baseline, lines), 0);
+        let more: HashSet<String> = vec!["/src/a.c:1".to_owned(), "/src/c.c:9".to_owned()]
+            .into_iter()
+            .collect();
+        assert_eq!(add_to_baseline(&mut baseline, more), 1);
+    }
+}
@@ -3,13 +3,14 @@ use std::error::Error;
 use tracing::error;
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
-use tonic::Request;
 
 use super::executor::ExecutorConfig;
 use crate::common::intervals::WORKER_FUZZDRIVER_STAT_UPLOAD_INTERVAL;
+use crate::common::outbox::submit_or_buffer;
 use crate::common::xpc::get_orchestrator_client;
 use crate::fuzz_driver::FuzzConfig;
 use crate::models::NewFuzzStat;
+use crate::xpc::OutboxEntry;
 
 mod lcov;
 
@@ -45,10 +46,8 @@ pub trait FuzzStatCollector: Send + Sync {
 
     async fn main_loop(self: Box<Self>) -> Result<(), Box<dyn Error>> {
         let mut interval = tokio::time::interval(self.get_refresh_duration());
-        let client = &get_orchestrator_client().await?;
         loop {
             interval.tick().await;
-            let mut client = client.clone();
             // Iterate over logs and get stats
             let stat: Option<NewFuzzStat> = match self.get_stat().await {
                 Ok(stat) => stat,
@@ -58,8 +57,10 @@ pub trait FuzzStatCollector: Send + Sync {
                 }
             };
 
+            // Client is taken every time, so that backoff & reloaded master settings are picked up
             if let Some(stat) = stat {
-                if let Err(e) = client.submit_fuzz_stat(Request::new(stat)).await {
+                let mut client = get_orchestrator_client().await.ok();
+                if let Err(e) = submit_or_buffer(OutboxEntry::fuzz_stat(stat), client.as_mut()).await {
                     error!("Failed to submit a fuzz stat: {}", e);
                 }
             }
@@ -1,23 +1,66 @@
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 use tonic::{Code, Request, Response, Status};
 
 use crate::db::DbBroker;
-use crate::models::{NewWorker, Worker};
-use crate::schema::workers;
+use crate::models::{NewWorker, Worker, WorkerTask};
+use crate::schema::{worker_tasks, workers};
 use crate::xpc::collector_server::Collector;
+use crate::xpc::{PatchWorkerTask, RunningWorkerTask};
 
 #[derive(Clone)]
 pub struct CollectorService {
     db_broker: DbBroker,
 }
 
+/// Stored part of NewWorker, running tasks it carries in heartbeats have no column
+#[derive(Insertable, AsChangeset)]
+#[table_name = "workers"]
+struct NewWorkerRow {
+    uuid: String,
+    name: Option<String>,
+    cpus: i32,
+    memory: i32,
+    active: bool,
+    docker: Option<bool>,
+    podman: Option<bool>,
+    kvm: Option<bool>,
+    disk_free: Option<i32>,
+    kernel: Option<String>,
+    arch: Option<String>,
+    fuzzers: Vec<String>,
+    labels: Vec<String>,
+}
+
+impl From<NewWorker> for NewWorkerRow {
+    fn from(new_worker: NewWorker) -> Self {
+        Self {
+            uuid: new_worker.uuid,
+            name: new_worker.name,
+            cpus: new_worker.cpus,
+            memory: new_worker.memory,
+            active: new_worker.active,
+            docker: new_worker.docker,
+            podman: new_worker.podman,
+            kvm: new_worker.kvm,
+            disk_free: new_worker.disk_free,
+            kernel: new_worker.kernel,
+            arch: new_worker.arch,
+            fuzzers: new_worker.fuzzers,
+            labels: new_worker.labels,
+        }
+    }
+}
+
 #[tonic::async_trait]
 impl Collector for CollectorService {
     async fn heartbeat(&self, request: Request<NewWorker>) -> Result<Response<Worker>, Status> {
         // First get inner type of tonic::Request & then use our From traits
-        let new_worker: NewWorker = request.into_inner();
+        let mut new_worker: NewWorker = request.into_inner();
         debug!("Received a heartbeat request from {}", new_worker.uuid);
+        let running_tasks = std::mem::take(&mut new_worker.running_tasks);
+        let new_worker = NewWorkerRow::from(new_worker);
 
         debug!("Inserting agent into database");
         // Get connection from pool (r2d2)
@@ -36,6 +79,19 @@ impl Collector for CollectorService {
             Err(Status::new(Code::Internal, format!("{}", e)))
         } else {
             let worker = worker.unwrap().pop().unwrap();
+
+            // Worker is shutting down gracefully, so its tasks can be scheduled elsewhere right away
+            if worker.active == false {
+                debug!("Worker {} is leaving, deactivating its worker tasks", worker.id);
+                if let Err(e) = diesel::update(WorkerTask::belonging_to(&worker))
+                    .set(worker_tasks::active.eq(false))
+                    .execute(&conn)
+                {
+                    error!("Unable to deactivate worker tasks of {}: {}", worker.id, e);
+                }
+            } else if let Err(e) = reconcile_worker_tasks(&worker, &running_tasks, &conn) {
+                error!("Unable to reconcile worker tasks of {}: {}", worker.id, e);
+            }
             Ok(Response::new(worker))
         }
     }
@@ -46,3 +102,60 @@ impl CollectorService {
         CollectorService { db_broker }
     }
 }
+
+/// Updates running state & restarts of worker tasks from what worker actually runs, as separate
+/// updates from worker can get lost. Zombies (running but deactivated) & orphans (unknown to us) are
+/// only reported, worker removes them on its next refresh anyway
+fn reconcile_worker_tasks(
+    worker: &Worker,
+    running_tasks: &[RunningWorkerTask],
+    conn: &PgConnection,
+) -> Result<(), diesel::result::Error> {
+    let worker_tasks = WorkerTask::belonging_to(worker).load::<WorkerTask>(conn)?;
+
+    for running_task in running_tasks.iter() {
+        match worker_tasks.iter().find(|wt| wt.id == running_task.worker_task_id) {
+            Some(worker_task) => {
+                if worker_task.active == false {
+                    warn!(
+                        "Worker {} is still running inactive worker task {}",
+                        worker.id, worker_task.id
+                    );
+                }
+                // Process is missing while fuzz driver is setting up or respawning, running state is
+                // left to driver till then
+                let running = worker_task.running || running_task.process.is_some();
+                if worker_task.running != running || worker_task.restarts != running_task.restarts {
+                    let patch_worker_task = PatchWorkerTask {
+                        id: worker_task.id,
+                        running,
+                        restarts: Some(running_task.restarts),
+                    };
+                    diesel::update(worker_tasks::table)
+                        .filter(worker_tasks::id.eq(worker_task.id))
+                        .set(&patch_worker_task)
+                        .execute(conn)?;
+                }
+            }
+            None => warn!(
+                "Worker {} is running worker task {} that isn't assigned to it",
+                worker.id, running_task.worker_task_id
+            ),
+        }
+    }
+
+    // Marked running, but worker doesn't have it anymore
+    for worker_task in worker_tasks.iter().filter(|wt| wt.running) {
+        if running_tasks.iter().any(|rt| rt.worker_task_id == worker_task.id) == false {
+            debug!(
+                "Worker task {} is no longer running on worker {}",
+                worker_task.id, worker.id
+            );
+            diesel::update(worker_tasks::table)
+                .filter(worker_tasks::id.eq(worker_task.id))
+                .set(worker_tasks::running.eq(false))
+                .execute(conn)?;
+        }
+    }
+    Ok(())
+}
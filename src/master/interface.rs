@@ -1,17 +1,31 @@
+use std::collections::HashSet;
 use std::time::{Duration, UNIX_EPOCH};
 
 use diesel::prelude::*;
+use diesel::sql_types::{Binary, Integer, Text};
 use tracing::{debug, error};
 use tonic::{Code, Request, Response, Status};
 
 use crate::common::profiles::construct_profile;
 use crate::db::DbBroker;
-use crate::models::{Corpus, Crash, NewCorpus, NewCrash, NewFuzzStat, NewTask, PatchCrash, Task};
-use crate::schema::{corpora, crashes, fuzz_stats, sys_stats, tasks, worker_tasks, workers, trace_events};
+use crate::models::{
+    Artifact, ArtifactInfo, Corpus, CorpusSnapshot, Crash, CrashVariant, NewArtifact, NewCorpus, NewCrash,
+    NewCrashVariant, NewFuzzStat, NewTask, PatchCrash, Task, WorkerTaskLog,
+};
+use crate::schema::{
+    artifacts, corpora, corpus_snapshot_entries, corpus_snapshots, crash_variants, crashes, fuzz_stats, sys_stats,
+    tasks, trace_events, worker_task_logs, worker_tasks, workers,
+};
+use crate::utils::checksum;
 use crate::xpc;
 use crate::xpc::orchestrator_server::Orchestrator;
 pub use crate::xpc::orchestrator_server::OrchestratorServer;
 
+// Lines of fuzzer output sent when no limit is asked for
+const WORKER_TASK_LOGS_LIMIT: i64 = 100;
+// Only these many latest lines are kept per worker task, full output stays in log files on worker
+const WORKER_TASK_LOGS_RETAINED: i64 = 10_000;
+
 #[derive(Clone)]
 pub struct OrchestratorService {
     db_broker: DbBroker,
@@ -158,12 +172,58 @@ impl Orchestrator for OrchestratorService {
             query = query.filter(corpora::worker_task_id.eq(worker_task_id));
         }
 
-        // If limit is present, sort by latest
-        if let Some(limit) = filter_corpus.latest {
-            query = query.order(corpora::created_at.desc()).limit(limit);
+        if let Some(min_size) = filter_corpus.min_size {
+            query = query.filter(corpora::size.ge(min_size));
+        }
+        if let Some(max_size) = filter_corpus.max_size {
+            query = query.filter(corpora::size.le(max_size));
+        }
+        if let Some(driver) = filter_corpus.driver {
+            query = query.filter(corpora::driver.ilike(driver));
+        }
+
+        let with_coverage = filter_corpus.with_coverage.unwrap_or(false);
+        if with_coverage {
+            query = query.filter(corpora::coverage.gt(0));
+        }
+        if filter_corpus.checksums.len() > 0 {
+            query = query.filter(corpora::checksum.eq_any(filter_corpus.checksums));
+        }
+
+        // If limit is present, sort by latest or by most coverage added. Paging is always by id
+        if let Some(after_id) = filter_corpus.after_id {
+            query = query.filter(corpora::id.gt(after_id)).order(corpora::id.asc());
+            if let Some(limit) = filter_corpus.latest {
+                query = query.limit(limit);
+            }
+        } else if let Some(limit) = filter_corpus.latest {
+            if with_coverage {
+                query = query
+                    .order((corpora::coverage.desc(), corpora::created_at.desc()))
+                    .limit(limit);
+            } else {
+                query = query.order(corpora::created_at.desc()).limit(limit);
+            }
         }
 
-        let corpus_list = query.load::<Corpus>(&conn);
+        // Content is left out of select rather than loaded & dropped, as it is most of row
+        let corpus_list = if filter_corpus.without_content.unwrap_or(false) {
+            query
+                .select((
+                    corpora::id,
+                    Vec::<u8>::new().into_sql::<Binary>(),
+                    corpora::checksum,
+                    corpora::label,
+                    corpora::worker_task_id,
+                    corpora::created_at,
+                    corpora::size,
+                    corpora::driver,
+                    corpora::coverage,
+                ))
+                .load::<Corpus>(&conn)
+        } else {
+            query.load::<Corpus>(&conn)
+        };
 
         if let Err(e) = corpus_list {
             error!("Unable to get corpus: {}", e);
@@ -198,27 +258,306 @@ impl Orchestrator for OrchestratorService {
         }
     }
 
+    async fn update_corpus_coverage(&self, request: Request<xpc::CorpusCoverage>) -> Result<Response<()>, Status> {
+        let corpus_coverage = request.into_inner();
+        debug!("Updating coverage of corpus: {:?}", corpus_coverage);
+
+        let conn = self.db_broker.get_conn();
+        let query = corpora::table.filter(corpora::id.eq_any(corpus_coverage.ids));
+        let result = diesel::update(query)
+            .set(corpora::coverage.eq(corpus_coverage.coverage))
+            .execute(&conn);
+
+        if let Err(e) = result {
+            error!("Unable to update corpus coverage: {}", e);
+            Err(Status::new(Code::InvalidArgument, format!("{}", e)))
+        } else {
+            Ok(Response::new({}))
+        }
+    }
+
+    // Corpus snapshot related calls
+    async fn submit_corpus_snapshot(
+        &self,
+        request: Request<xpc::NewCorpusSnapshot>,
+    ) -> Result<Response<xpc::CorpusSnapshot>, Status> {
+        let new_snapshot = request.into_inner();
+        debug!("Creating corpus snapshot: {:?}", new_snapshot);
+
+        let conn = self.db_broker.get_conn();
+        let snapshot = conn.transaction::<_, diesel::result::Error, _>(|| {
+            let snapshot = diesel::insert_into(corpus_snapshots::table)
+                .values(&new_snapshot)
+                .get_result::<CorpusSnapshot>(&conn)?;
+
+            // Copy contents as corpus can be deleted later on, done by database so that content of a
+            // whole label never has to pass through master
+            let corpus_list = corpora::table.filter(corpora::label.eq(&snapshot.label)).select((
+                snapshot.id.into_sql::<Integer>(),
+                corpora::content,
+                corpora::checksum,
+                corpora::worker_task_id,
+                corpora::size,
+                corpora::driver,
+                corpora::coverage,
+            ));
+            diesel::insert_into(corpus_snapshot_entries::table)
+                .values(corpus_list)
+                .into_columns((
+                    corpus_snapshot_entries::snapshot_id,
+                    corpus_snapshot_entries::content,
+                    corpus_snapshot_entries::checksum,
+                    corpus_snapshot_entries::worker_task_id,
+                    corpus_snapshot_entries::size,
+                    corpus_snapshot_entries::driver,
+                    corpus_snapshot_entries::coverage,
+                ))
+                .execute(&conn)?;
+            Ok(snapshot)
+        });
+
+        if let Err(e) = snapshot {
+            error!("Unable to create corpus snapshot: {}", e);
+            Err(Status::new(Code::InvalidArgument, format!("{}", e)))
+        } else {
+            Ok(Response::new(snapshot.unwrap()))
+        }
+    }
+
+    async fn get_corpus_snapshots(
+        &self,
+        request: Request<xpc::FilterCorpusSnapshot>,
+    ) -> Result<Response<xpc::CorpusSnapshots>, Status> {
+        let filter_snapshot = request.into_inner();
+        debug!("Filtering and sending corpus snapshots {:?}", filter_snapshot);
+
+        let conn = self.db_broker.get_conn();
+        let mut query = corpus_snapshots::table.into_boxed();
+
+        if let Some(label) = filter_snapshot.label {
+            query = query.filter(corpus_snapshots::label.ilike(label));
+        }
+
+        if let Some(name) = filter_snapshot.name {
+            query = query.filter(corpus_snapshots::name.ilike(name));
+        }
+
+        let snapshot_list = query
+            .order(corpus_snapshots::created_at.asc())
+            .load::<CorpusSnapshot>(&conn);
+
+        if let Err(e) = snapshot_list {
+            error!("Unable to get corpus snapshots: {}", e);
+            Err(Status::new(Code::NotFound, ""))
+        } else {
+            Ok(Response::new(xpc::CorpusSnapshots {
+                data: snapshot_list.unwrap(),
+            }))
+        }
+    }
+
+    async fn restore_corpus_snapshot(
+        &self,
+        request: Request<xpc::RestoreCorpusSnapshot>,
+    ) -> Result<Response<()>, Status> {
+        let restore_snapshot = request.into_inner();
+        debug!("Restoring corpus snapshot: {:?}", restore_snapshot);
+
+        let conn = self.db_broker.get_conn();
+        let result = conn.transaction::<_, diesel::result::Error, _>(|| {
+            let snapshot = corpus_snapshots::table
+                .find(restore_snapshot.id)
+                .first::<CorpusSnapshot>(&conn)?;
+
+            // Remove anything that was added to label after snapshot
+            if restore_snapshot.merge == false {
+                let checksums = corpus_snapshot_entries::table
+                    .filter(corpus_snapshot_entries::snapshot_id.eq(snapshot.id))
+                    .select(corpus_snapshot_entries::checksum)
+                    .load::<String>(&conn)?;
+                let query = corpora::table.filter(
+                    corpora::label
+                        .eq(&snapshot.label)
+                        .and(corpora::checksum.ne_all(checksums)),
+                );
+                diesel::delete(query).execute(&conn)?;
+            }
+
+            // Restored corpus get a new created_at, so that workers sync them back again. Ones still
+            // present in label are left as they are
+            let present = corpora::table
+                .filter(corpora::label.eq(&snapshot.label))
+                .select(corpora::checksum);
+            let entries = corpus_snapshot_entries::table
+                .filter(corpus_snapshot_entries::snapshot_id.eq(snapshot.id))
+                .filter(diesel::dsl::not(corpus_snapshot_entries::checksum.eq_any(present)))
+                .select((
+                    corpus_snapshot_entries::content,
+                    corpus_snapshot_entries::checksum,
+                    snapshot.label.clone().into_sql::<Text>(),
+                    corpus_snapshot_entries::worker_task_id,
+                    corpus_snapshot_entries::size,
+                    corpus_snapshot_entries::driver,
+                    corpus_snapshot_entries::coverage,
+                ));
+            diesel::insert_into(corpora::table)
+                .values(entries)
+                .into_columns((
+                    corpora::content,
+                    corpora::checksum,
+                    corpora::label,
+                    corpora::worker_task_id,
+                    corpora::size,
+                    corpora::driver,
+                    corpora::coverage,
+                ))
+                .execute(&conn)?;
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            error!("Unable to restore corpus snapshot: {}", e);
+            Err(Status::new(Code::InvalidArgument, format!("{}", e)))
+        } else {
+            Ok(Response::new({}))
+        }
+    }
+
+    async fn diff_corpus_snapshot(
+        &self,
+        request: Request<xpc::DiffCorpusSnapshot>,
+    ) -> Result<Response<xpc::CorpusSnapshotDiff>, Status> {
+        let diff_snapshot = request.into_inner();
+        debug!("Diffing corpus snapshot: {:?}", diff_snapshot);
+
+        let conn = self.db_broker.get_conn();
+        let checksums = |snapshot_id: i32| {
+            corpus_snapshot_entries::table
+                .filter(corpus_snapshot_entries::snapshot_id.eq(snapshot_id))
+                .select(corpus_snapshot_entries::checksum)
+                .load::<String>(&conn)
+        };
+
+        let result = corpus_snapshots::table
+            .find(diff_snapshot.id)
+            .first::<CorpusSnapshot>(&conn)
+            .and_then(|snapshot| {
+                let old = checksums(snapshot.id)?;
+                let new = if let Some(against_id) = diff_snapshot.against_id {
+                    checksums(against_id)?
+                } else {
+                    corpora::table
+                        .filter(corpora::label.eq(&snapshot.label))
+                        .select(corpora::checksum)
+                        .load::<String>(&conn)?
+                };
+                Ok((old, new))
+            });
+
+        if let Err(e) = result {
+            error!("Unable to diff corpus snapshot: {}", e);
+            Err(Status::new(Code::NotFound, format!("{}", e)))
+        } else {
+            let (old, new) = result.unwrap();
+            Ok(Response::new(diff_checksums(old, new)))
+        }
+    }
+
+    async fn delete_corpus_snapshot(&self, request: Request<xpc::Id>) -> Result<Response<()>, Status> {
+        let id = request.into_inner();
+        debug!("Deleting corpus snapshot: {:?}", id);
+
+        let conn = self.db_broker.get_conn();
+        let result = diesel::delete(corpus_snapshots::table.find(id.value)).execute(&conn);
+
+        if let Err(e) = result {
+            error!("Unable to delete corpus snapshot: {}", e);
+            Err(Status::new(Code::NotFound, ""))
+        } else {
+            Ok(Response::new({}))
+        }
+    }
+
     // Crash related calls
-    async fn submit_crash(&self, request: Request<NewCrash>) -> Result<Response<()>, Status> {
+    async fn submit_crash(&self, request: Request<NewCrash>) -> Result<Response<xpc::Id>, Status> {
         debug!("Received new crash");
 
         let new_crash: NewCrash = request.into_inner();
 
+        // Upload retried after a timeout might already be in, its id is returned so that variants of
+        // crash are still submitted
         let conn = self.db_broker.get_conn();
-        let rows_inserted = diesel::insert_into(crashes::table)
+        let crash_id = diesel::insert_into(crashes::table)
             .values(&new_crash)
+            .on_conflict((crashes::checksum, crashes::label, crashes::worker_task_id))
+            .do_nothing()
             .returning(crashes::id)
-            .execute(&conn);
+            .get_result::<i32>(&conn)
+            .optional()
+            .and_then(|crash_id| match crash_id {
+                Some(crash_id) => Ok(crash_id),
+                None => crashes::table
+                    .filter(crashes::checksum.eq(&new_crash.checksum))
+                    .filter(crashes::label.eq(&new_crash.label))
+                    .filter(crashes::worker_task_id.eq(new_crash.worker_task_id))
+                    .select(crashes::id)
+                    .first::<i32>(&conn),
+            });
 
         // Failure of constraint will be logged here
-        if let Err(e) = rows_inserted {
+        if let Err(e) = crash_id {
             error!("Unable to add crash : {}", e);
             Err(Status::new(Code::InvalidArgument, format!("{}", e)))
+        } else {
+            Ok(Response::new(xpc::Id {
+                value: crash_id.unwrap(),
+            }))
+        }
+    }
+
+    async fn submit_crash_variant(&self, request: Request<NewCrashVariant>) -> Result<Response<()>, Status> {
+        let new_variant = request.into_inner();
+        debug!(
+            "Received {} variant for crash {}",
+            new_variant.variant, new_variant.crash_id
+        );
+
+        // Revalidation replaces earlier result of same variant
+        let conn = self.db_broker.get_conn();
+        let rows_inserted = diesel::insert_into(crash_variants::table)
+            .values(&new_variant)
+            .on_conflict((crash_variants::crash_id, crash_variants::variant))
+            .do_update()
+            .set(&new_variant)
+            .execute(&conn);
+
+        if let Err(e) = rows_inserted {
+            error!("Unable to add crash variant : {}", e);
+            Err(Status::new(Code::InvalidArgument, format!("{}", e)))
         } else {
             Ok(Response::new({}))
         }
     }
 
+    async fn get_crash_variants(&self, request: Request<xpc::Id>) -> Result<Response<xpc::CrashVariants>, Status> {
+        let id = request.into_inner();
+
+        let conn = self.db_broker.get_conn();
+        let variant_list = crash_variants::table
+            .filter(crash_variants::crash_id.eq(id.value))
+            .order(crash_variants::variant.asc())
+            .load::<CrashVariant>(&conn);
+
+        if let Err(e) = variant_list {
+            error!("Unable to get crash variants: {}", e);
+            Err(Status::new(Code::NotFound, ""))
+        } else {
+            Ok(Response::new(xpc::CrashVariants {
+                data: variant_list.unwrap(),
+            }))
+        }
+    }
+
     async fn update_crash(&self, request: Request<PatchCrash>) -> Result<Response<()>, Status> {
         let patch_crash: PatchCrash = request.into_inner();
 
@@ -377,6 +716,161 @@ impl Orchestrator for OrchestratorService {
         }
     }
 
+    async fn submit_worker_task_logs(&self, request: Request<xpc::NewWorkerTaskLogs>) -> Result<Response<()>, Status> {
+        let new_logs = request.into_inner();
+
+        let conn = self.db_broker.get_conn();
+        let rows_inserted = diesel::insert_into(worker_task_logs::table)
+            .values(&new_logs.data)
+            .execute(&conn);
+
+        if let Err(e) = rows_inserted {
+            error!("Unable to add worker task logs : {}", e);
+            return Err(Status::new(Code::InvalidArgument, format!("{}", e)));
+        }
+
+        // Prune older lines of worker tasks that just got some
+        let worker_task_ids: HashSet<i32> = new_logs.data.iter().map(|log| log.worker_task_id).collect();
+        for worker_task_id in worker_task_ids {
+            let oldest_retained = worker_task_logs::table
+                .filter(worker_task_logs::worker_task_id.eq(worker_task_id))
+                .select(worker_task_logs::id)
+                .order(worker_task_logs::id.desc())
+                .offset(WORKER_TASK_LOGS_RETAINED - 1)
+                .first::<i32>(&conn)
+                .optional();
+            let rows_deleted = match oldest_retained {
+                Ok(Some(oldest_retained)) => diesel::delete(
+                    worker_task_logs::table
+                        .filter(worker_task_logs::worker_task_id.eq(worker_task_id))
+                        .filter(worker_task_logs::id.lt(oldest_retained)),
+                )
+                .execute(&conn),
+                Ok(None) => Ok(0),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = rows_deleted {
+                error!("Unable to prune logs of worker task {}: {}", worker_task_id, e);
+            }
+        }
+        Ok(Response::new({}))
+    }
+
+    async fn get_worker_task_logs(
+        &self,
+        request: Request<xpc::FilterWorkerTaskLog>,
+    ) -> Result<Response<xpc::WorkerTaskLogs>, Status> {
+        let filter_log = request.into_inner();
+        debug!("Filtering and sending worker task logs {:?}", filter_log);
+
+        let conn = self.db_broker.get_conn();
+        let limit = filter_log.limit.unwrap_or(WORKER_TASK_LOGS_LIMIT);
+        let query = worker_task_logs::table
+            .filter(worker_task_logs::worker_task_id.eq(filter_log.worker_task_id))
+            .limit(limit)
+            .into_boxed();
+
+        // Following picks up after last seen line, otherwise tail is sent
+        let logs = if let Some(after_id) = filter_log.after_id {
+            query
+                .filter(worker_task_logs::id.gt(after_id))
+                .order(worker_task_logs::id.asc())
+                .load::<WorkerTaskLog>(&conn)
+        } else {
+            query
+                .order(worker_task_logs::id.desc())
+                .load::<WorkerTaskLog>(&conn)
+                .map(|mut logs| {
+                    logs.reverse();
+                    logs
+                })
+        };
+
+        if let Err(e) = logs {
+            error!("Unable to get worker task logs: {}", e);
+            Err(Status::new(Code::NotFound, ""))
+        } else {
+            Ok(Response::new(xpc::WorkerTaskLogs { data: logs.unwrap() }))
+        }
+    }
+
+    // Artifact related calls
+    async fn submit_artifact(&self, request: Request<NewArtifact>) -> Result<Response<()>, Status> {
+        let new_artifact = request.into_inner();
+        debug!("Received artifact {}:{}", new_artifact.name, new_artifact.version);
+
+        // Workers verify again after download, but don't even store a broken one
+        if checksum(&new_artifact.content) != new_artifact.checksum {
+            return Err(Status::new(Code::InvalidArgument, "Checksum mismatch for artifact"));
+        }
+
+        let conn = self.db_broker.get_conn();
+        let rows_inserted = diesel::insert_into(artifacts::table)
+            .values(&new_artifact)
+            .execute(&conn);
+
+        if let Err(e) = rows_inserted {
+            error!("Unable to add artifact : {}", e);
+            Err(Status::new(Code::InvalidArgument, format!("{}", e)))
+        } else {
+            Ok(Response::new({}))
+        }
+    }
+
+    async fn get_artifacts(
+        &self,
+        request: Request<xpc::FilterArtifact>,
+    ) -> Result<Response<xpc::ArtifactInfos>, Status> {
+        let filter_artifact = request.into_inner();
+        debug!("Filtering and sending artifacts {:?}", filter_artifact);
+
+        let conn = self.db_broker.get_conn();
+        let mut query = artifacts::table.into_boxed();
+
+        if let Some(name) = filter_artifact.name {
+            query = query.filter(artifacts::name.eq(name));
+        }
+
+        if let Some(version) = filter_artifact.version {
+            query = query.filter(artifacts::version.eq(version));
+        }
+
+        // Latest first, so that a name without version resolves to newest upload
+        let artifact_list = query
+            .select((
+                artifacts::id,
+                artifacts::name,
+                artifacts::version,
+                artifacts::checksum,
+                artifacts::created_at,
+            ))
+            .order(artifacts::created_at.desc())
+            .load::<ArtifactInfo>(&conn);
+
+        if let Err(e) = artifact_list {
+            error!("Unable to get artifacts: {}", e);
+            Err(Status::new(Code::NotFound, ""))
+        } else {
+            Ok(Response::new(xpc::ArtifactInfos {
+                data: artifact_list.unwrap(),
+            }))
+        }
+    }
+
+    async fn fetch_artifact(&self, request: Request<xpc::Id>) -> Result<Response<Artifact>, Status> {
+        let id = request.into_inner();
+
+        let conn = self.db_broker.get_conn();
+        let artifact = artifacts::table.find(id.value).first::<Artifact>(&conn);
+
+        if let Err(e) = artifact {
+            error!("Unable to fetch artifact: {}", e);
+            Err(Status::new(Code::NotFound, format!("{}", e)))
+        } else {
+            Ok(Response::new(artifact.unwrap()))
+        }
+    }
+
     // Fuzz stat related calls
     async fn submit_fuzz_stat(&self, request: Request<NewFuzzStat>) -> Result<Response<()>, Status> {
         let new_fuzz_stat = request.into_inner();
@@ -437,3 +931,34 @@ impl OrchestratorService {
         Self { db_broker }
     }
 }
+
+/// Checksums added & removed going from old to new, sorted so that output is stable
+fn diff_checksums(old: Vec<String>, new: Vec<String>) -> xpc::CorpusSnapshotDiff {
+    let old: HashSet<String> = old.into_iter().collect();
+    let new: HashSet<String> = new.into_iter().collect();
+
+    let mut added: Vec<String> = new.difference(&old).cloned().collect();
+    let mut removed: Vec<String> = old.difference(&new).cloned().collect();
+    added.sort();
+    removed.sort();
+
+    xpc::CorpusSnapshotDiff { added, removed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_diff_checksums() {
+        let to_vec = |checksums: &[&str]| checksums.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+
+        let diff = diff_checksums(to_vec(&["b", "a", "c"]), to_vec(&["d", "c", "a", "e"]));
+        assert_eq!(diff.added, to_vec(&["d", "e"]));
+        assert_eq!(diff.removed, to_vec(&["b"]));
+
+        // Duplicates don't show up twice & nothing changed means empty diff
+        let diff = diff_checksums(to_vec(&["a", "a"]), to_vec(&["a"]));
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+    }
+}
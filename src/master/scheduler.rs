@@ -7,6 +7,8 @@ use tracing::{debug, error, trace, warn};
 
 use crate::common::profiles::construct_profile;
 use crate::db::DbBroker;
+use crate::executor::ExecutorEnum;
+use crate::fuzz_driver::FuzzConfig;
 use crate::models::{Task, Worker, WorkerTask};
 use crate::schema::{trace_events, sys_stats, tasks, worker_tasks, workers};
 
@@ -164,8 +166,14 @@ impl Scheduler {
     //
     // 1. Relevant worker is still active
     // 2. There are free cpus left
+    // 3. Worker still has capabilities needed by profile
     //
-    fn get_activatable_worker_task(&self, task: &Task, requirement: i32) -> Result<Option<WorkerTask>, Box<dyn Error>> {
+    fn get_activatable_worker_task(
+        &self,
+        task: &Task,
+        profile: &FuzzConfig,
+        requirement: i32,
+    ) -> Result<Option<WorkerTask>, Box<dyn Error>> {
         let conn = self.db_broker.get_conn();
 
         let worker_tasks = WorkerTask::belonging_to(task)
@@ -180,6 +188,9 @@ impl Scheduler {
             .load::<(WorkerTask, Worker)>(&conn)?;
 
         for (worker_task, worker) in worker_tasks {
+            if get_missing_capability(&worker, profile).is_some() {
+                continue;
+            }
             if self.get_free_cpus(&worker)? >= requirement {
                 return Ok(Some(worker_task));
             }
@@ -208,7 +219,7 @@ impl Scheduler {
                 debug!("Got {} free workers", free_workers.len());
 
                 // 3. Check if existing inactive worker task can satisfy this
-                if let Some(worker_task) = self.get_activatable_worker_task(&task, new_requirement)? {
+                if let Some(worker_task) = self.get_activatable_worker_task(&task, &profile, new_requirement)? {
                     self.activate_worker_task(&worker_task)?;
                     continue;
                 }
@@ -216,11 +227,15 @@ impl Scheduler {
                 // 4. If not create a new worker task
                 let worker = free_workers.iter().find_map(|w_f| {
                     let (worker, free_cpus) = w_f;
-                    if free_cpus >= &new_requirement {
-                        Some(worker)
-                    } else {
-                        None
+                    if free_cpus < &new_requirement {
+                        return None;
+                    }
+                    // Placing it anyway would only fail at executor setup on worker
+                    if let Some(missing) = get_missing_capability(worker, &profile) {
+                        debug!("Worker {} lacks {} needed by task {}", worker.id, missing, task.id);
+                        return None;
                     }
+                    Some(worker)
                 });
                 if let Some(worker) = worker {
                     self.add_worker_task(&task, &worker, new_requirement)?;
@@ -283,3 +298,141 @@ impl Scheduler {
         }
     }
 }
+
+/// What worker lacks to run profile, capabilities not reported by older workers are assumed present
+fn get_missing_capability(worker: &Worker, profile: &FuzzConfig) -> Option<String> {
+    for exec_config in profile.get_executor_configs() {
+        let usable = match exec_config.executor {
+            ExecutorEnum::Docker => worker.docker,
+            ExecutorEnum::Podman => worker.podman,
+            ExecutorEnum::Native | ExecutorEnum::Sandbox => None,
+        };
+        if usable == Some(false) {
+            return Some(format!("{:?} executor", exec_config.executor));
+        }
+    }
+
+    let requirements = &profile.requirements;
+    if let (Some(arch), Some(worker_arch)) = (requirements.arch.as_ref(), worker.arch.as_ref()) {
+        if arch != worker_arch {
+            return Some(format!("{} arch", arch));
+        }
+    }
+
+    if requirements.kvm && worker.kvm == Some(false) {
+        return Some("kvm".to_owned());
+    }
+
+    if let (Some(disk_free), Some(worker_disk_free)) = (requirements.disk_free, worker.disk_free) {
+        if worker_disk_free < disk_free {
+            return Some(format!("{} MB of free disk", disk_free));
+        }
+    }
+
+    // Arch is always reported along with fuzzers, so empty fuzzers of older workers are not held against them
+    if worker.arch.is_some() {
+        if let Some(fuzzer) = requirements
+            .fuzzers
+            .iter()
+            .find(|f| worker.fuzzers.contains(f) == false)
+        {
+            return Some(format!("{} fuzzer", fuzzer));
+        }
+    }
+
+    if let Some(label) = requirements.labels.iter().find(|l| worker.labels.contains(l) == false) {
+        return Some(format!("{} label", label));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_get_missing_capability() {
+        let mut profile: FuzzConfig = serde_yaml::from_str(
+            r#"
+driver: Libfuzzer
+execution:
+  executor: Docker
+  cpus: 1
+  image: fuzzer
+  executable: /fuzzer
+  cwd: /fuzzing
+corpus:
+  path: corpus
+  label: target
+  refresh_interval: 60
+  upload: true
+  upload_filter: ".*"
+crash:
+  path: "."
+  label: target
+  filter: "crash-.*"
+requirements:
+  arch: x86_64
+  kvm: true
+  disk_free: 1024
+  fuzzers: [honggfuzz]
+"#,
+        )
+        .unwrap();
+
+        // Nothing reported by older workers is held against them
+        let mut worker = Worker::default();
+        assert_eq!(get_missing_capability(&worker, &profile), None);
+
+        worker.docker = Some(false);
+        assert_eq!(
+            get_missing_capability(&worker, &profile),
+            Some("Docker executor".to_owned())
+        );
+        worker.docker = Some(true);
+
+        worker.arch = Some("aarch64".to_owned());
+        assert_eq!(
+            get_missing_capability(&worker, &profile),
+            Some("x86_64 arch".to_owned())
+        );
+        worker.arch = Some("x86_64".to_owned());
+
+        worker.kvm = Some(false);
+        assert_eq!(get_missing_capability(&worker, &profile), Some("kvm".to_owned()));
+        worker.kvm = Some(true);
+
+        worker.disk_free = Some(512);
+        assert_eq!(
+            get_missing_capability(&worker, &profile),
+            Some("1024 MB of free disk".to_owned())
+        );
+        worker.disk_free = Some(2048);
+
+        assert_eq!(
+            get_missing_capability(&worker, &profile),
+            Some("honggfuzz fuzzer".to_owned())
+        );
+        worker.fuzzers = vec!["honggfuzz".to_owned()];
+        assert_eq!(get_missing_capability(&worker, &profile), None);
+
+        // Labels come only from worker config, so workers without them never match
+        profile.requirements.labels = vec!["team-a".to_owned()];
+        assert_eq!(
+            get_missing_capability(&worker, &profile),
+            Some("team-a label".to_owned())
+        );
+        worker.labels = vec!["team-a".to_owned(), "team-b".to_owned()];
+        assert_eq!(get_missing_capability(&worker, &profile), None);
+
+        // Helper executors need to be usable as well
+        profile.execution.executor = ExecutorEnum::Native;
+        let mut validate = profile.execution.clone();
+        validate.executor = ExecutorEnum::Podman;
+        profile.crash.validate = Some(validate);
+        worker.podman = Some(false);
+        assert_eq!(
+            get_missing_capability(&worker, &profile),
+            Some("Podman executor".to_owned())
+        );
+    }
+}
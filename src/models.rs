@@ -1,4 +1,5 @@
 pub use crate::xpc::{
-    Corpus, Crash, NewCorpus, NewCrash, NewFuzzStat, NewSysStat, NewTask, NewWorker, PatchCrash, PatchTask,
-    PatchWorkerTask, Task, Worker, WorkerTask, NewTraceEvent
+    Artifact, ArtifactInfo, Corpus, CorpusSnapshot, Crash, CrashVariant, NewArtifact, NewCorpus, NewCorpusSnapshot,
+    NewCrash, NewCrashVariant, NewFuzzStat, NewSysStat, NewTask, NewWorker, PatchCrash, PatchTask, PatchWorkerTask,
+    Task, Worker, WorkerTask, NewTraceEvent, NewWorkerTaskLog, WorkerTaskLog
 };
@@ -1,3 +1,14 @@
+table! {
+    artifacts (id) {
+        id -> Int4,
+        name -> Varchar,
+        version -> Varchar,
+        checksum -> Varchar,
+        content -> Bytea,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     corpora (id) {
         id -> Int4,
@@ -6,6 +17,43 @@ table! {
         label -> Varchar,
         worker_task_id -> Nullable<Int4>,
         created_at -> Timestamp,
+        size -> Int4,
+        driver -> Nullable<Varchar>,
+        coverage -> Nullable<Int4>,
+    }
+}
+
+table! {
+    corpus_snapshot_entries (id) {
+        id -> Int4,
+        snapshot_id -> Int4,
+        content -> Bytea,
+        checksum -> Varchar,
+        worker_task_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+        size -> Int4,
+        driver -> Nullable<Varchar>,
+        coverage -> Nullable<Int4>,
+    }
+}
+
+table! {
+    corpus_snapshots (id) {
+        id -> Int4,
+        name -> Varchar,
+        label -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    crash_variants (id) {
+        id -> Int4,
+        crash_id -> Int4,
+        variant -> Varchar,
+        verified -> Bool,
+        output -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
@@ -48,6 +96,8 @@ table! {
         swap_used -> Int4,
         worker_id -> Nullable<Int4>,
         created_at -> Timestamp,
+        disk_total -> Nullable<Int4>,
+        disk_free -> Nullable<Int4>,
     }
 }
 
@@ -73,6 +123,16 @@ table! {
     }
 }
 
+table! {
+    worker_task_logs (id) {
+        id -> Int4,
+        worker_task_id -> Int4,
+        stream -> Varchar,
+        line -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     worker_tasks (id) {
         id -> Int4,
@@ -82,6 +142,7 @@ table! {
         active -> Bool,
         running -> Bool,
         created_at -> Timestamp,
+        restarts -> Int4,
     }
 }
 
@@ -95,24 +156,41 @@ table! {
         active -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        docker -> Nullable<Bool>,
+        podman -> Nullable<Bool>,
+        kvm -> Nullable<Bool>,
+        disk_free -> Nullable<Int4>,
+        kernel -> Nullable<Varchar>,
+        arch -> Nullable<Varchar>,
+        fuzzers -> Array<Text>,
+        labels -> Array<Text>,
     }
 }
 
 joinable!(corpora -> worker_tasks (worker_task_id));
+joinable!(corpus_snapshot_entries -> corpus_snapshots (snapshot_id));
+joinable!(corpus_snapshot_entries -> worker_tasks (worker_task_id));
+joinable!(crash_variants -> crashes (crash_id));
 joinable!(crashes -> worker_tasks (worker_task_id));
 joinable!(fuzz_stats -> worker_tasks (worker_task_id));
 joinable!(sys_stats -> workers (worker_id));
 joinable!(trace_events -> workers (worker_id));
+joinable!(worker_task_logs -> worker_tasks (worker_task_id));
 joinable!(worker_tasks -> tasks (task_id));
 joinable!(worker_tasks -> workers (worker_id));
 
 allow_tables_to_appear_in_same_query!(
+    artifacts,
     corpora,
+    corpus_snapshot_entries,
+    corpus_snapshots,
+    crash_variants,
     crashes,
     fuzz_stats,
     sys_stats,
     tasks,
     trace_events,
+    worker_task_logs,
     worker_tasks,
     workers,
 );
@@ -1,10 +1,11 @@
 use std::error::Error;
 
+use tracing::{span, Event, Subscriber};
 use tracing_subscriber::{
     self,
     fmt,
     registry::Registry,
-    layer::{Layer, SubscriberExt},
+    layer::{Context, Layer, SubscriberExt},
     EnvFilter
 };
 
@@ -20,19 +21,12 @@ pub struct Tracer {
     verbose_n: u64
 }
 
-/// Heavy code dedup, fix this when implementing db tracing layer
 impl Tracer {
     pub fn new(verbose_n: u64) -> Self {
         Self { verbose_n }
     }
 
-    pub fn set_global_with_layer<L>(self, layer: L) -> Result<(), Box<dyn Error>>
-    where
-        L: Layer<Registry> + Send + Sync + Sized
-    {
-        let fmt_layer = fmt::layer()
-            .with_target(true);
-
+    fn get_env_filter(&self) -> Result<EnvFilter, Box<dyn Error>> {
         let env_filter = EnvFilter::from_default_env()
             .add_directive(match self.verbose_n {
                 1 => "fuzzy=info",
@@ -40,11 +34,20 @@ impl Tracer {
                 3 => "fuzzy=trace",
                 _ => "fuzzy=warn",
             }.parse()?);
+        Ok(env_filter)
+    }
+
+    /// Env filter only decides what is printed, given layer sees every event & filters on its own
+    pub fn set_global_with_layer<L>(self, layer: L) -> Result<(), Box<dyn Error>>
+    where
+        L: Layer<Registry> + Send + Sync + Sized
+    {
+        let fmt_layer = fmt::layer()
+            .with_target(true);
 
         let subscriber = Registry::default()
             .with(layer)
-            .with(env_filter)
-            .with(fmt_layer);
+            .with(FilteredLayer::new(self.get_env_filter()?, fmt_layer));
 
         tracing::subscriber::set_global_default(subscriber)?;
         Ok(())
@@ -54,19 +57,69 @@ impl Tracer {
         let fmt_layer = fmt::layer()
             .with_target(true);
 
-        let env_filter = EnvFilter::from_default_env()
-            .add_directive(match self.verbose_n {
-                1 => "fuzzy=info",
-                2 => "fuzzy=debug",
-                3 => "fuzzy=trace",
-                _ => "fuzzy=warn",
-            }.parse()?);
-
         let subscriber = Registry::default()
-            .with(env_filter)
+            .with(self.get_env_filter()?)
             .with(fmt_layer);
 
         tracing::subscriber::set_global_default(subscriber)?;
         Ok(())
     }
 }
+
+/// A filter added to subscriber disables events for every layer in it, this applies filter to wrapped
+/// layer alone. Filter still sees spans, as it can have directives on them
+struct FilteredLayer<L> {
+    filter: EnvFilter,
+    layer: L,
+}
+
+impl<L> FilteredLayer<L> {
+    fn new(filter: EnvFilter, layer: L) -> Self {
+        Self { filter, layer }
+    }
+}
+
+impl<S, L> Layer<S> for FilteredLayer<L>
+where
+    S: Subscriber,
+    L: Layer<S>,
+{
+    fn new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.new_span(attrs, id, ctx.clone());
+        self.layer.new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, span: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.filter.on_record(span, values, ctx.clone());
+        self.layer.on_record(span, values, ctx);
+    }
+
+    fn on_follows_from(&self, span: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.layer.on_follows_from(span, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if self.filter.enabled(event.metadata(), ctx.clone()) {
+            self.layer.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_enter(id, ctx.clone());
+        self.layer.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_exit(id, ctx.clone());
+        self.layer.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        self.filter.on_close(id.clone(), ctx.clone());
+        self.layer.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.layer.on_id_change(old, new, ctx);
+    }
+}
@@ -1,4 +1,6 @@
+use std::error::Error;
 use std::fmt;
+use std::sync::atomic::{AtomicI32, Ordering};
 
 use tokio::sync::mpsc::Sender;
 use tracing::field::{Visit, Field};
@@ -7,6 +9,31 @@ use tracing_subscriber::{registry::Registry, layer::{Context, Layer}};
 
 use crate::{trace::TraceEvent, models::NewTraceEvent};
 
+pub const DEFAULT_SHIPPING_LEVEL: &str = "warn";
+// Events at this level or more severe are shipped to master, only warnings and errors by default
+static SHIPPING_LEVEL: AtomicI32 = AtomicI32::new(3);
+// Outbox logs about buffering trace events, shipping those would feed them back into it
+const UNSHIPPED_TARGET: &str = "fuzzy::common::outbox";
+// Events of dependencies like tonic are left out, as shipping causes more of them
+const SHIPPED_TARGET_PREFIX: &str = "fuzzy";
+
+/// Takes a level name like warn or info, can be changed while running
+pub fn set_shipping_level(level: &str) -> Result<(), Box<dyn Error>> {
+    let level: Level = level.parse()?;
+    SHIPPING_LEVEL.store(get_level_number(&level), Ordering::Relaxed);
+    Ok(())
+}
+
+fn get_level_number(level: &Level) -> i32 {
+    match *level {
+        Level::ERROR => 2,
+        Level::WARN => 3,
+        Level::INFO => 4,
+        Level::DEBUG => 5,
+        Level::TRACE => 6,
+    }
+}
+
 pub struct NetworkLoggingLayer {
     tx: Sender<TraceEvent>
 }
@@ -35,16 +62,13 @@ impl Layer<Registry> for NetworkLoggingLayer {
     fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, Registry>) {
         let metadata = event.metadata();
 
-        let level = match *metadata.level() {
-            Level::ERROR => 2,
-            Level::WARN => 3,
-            Level::INFO => 4,
-            Level::DEBUG => 5,
-            Level::TRACE => 6,
-        };
+        let level = get_level_number(metadata.level());
 
-        if level < 4 {
-            // Only record warnings and errors
+        let target = metadata.target();
+        if level <= SHIPPING_LEVEL.load(Ordering::Relaxed)
+            && target.starts_with(SHIPPED_TARGET_PREFIX)
+            && target != UNSHIPPED_TARGET
+        {
             let mut new_trace_event = NewTraceEvent {
                 message: String::new(),
                 target: metadata.target().to_string(),
@@ -1,13 +1,14 @@
 use std::error::Error;
 use std::io::{BufRead, Seek, SeekFrom};
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, warn};
 use regex::Regex;
 
 #[cfg(target_os = "linux")]
-use inotify::{EventStream, Inotify, WatchMask};
+use inotify::{EventMask, EventStream, Inotify, WatchMask};
 
 use tokio::{
     fs::{self, File},
@@ -17,6 +18,21 @@ use tokio::{
 
 use crate::utils::get_human_dt;
 
+/// Finds an executable in PATH like `which` does
+pub fn which(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
 pub fn tail_n(file_path: &Path, bytes: u64) -> Result<Vec<String>, Box<dyn Error>> {
     let mut file = std::fs::File::open(file_path)?;
     let length = file.metadata()?.len();
@@ -51,19 +67,46 @@ pub async fn read_file(file_path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
     Ok(content)
 }
 
+/// Whether a file is wanted by a watcher. A missing whitelist matches nothing, so that a watcher set
+/// up without one never syncs a whole directory by accident. A missing blacklist skips nothing.
+fn is_filter_match(file_name: &str, blacklist_filter: Option<&Regex>, whitelist_filter: Option<&Regex>) -> bool {
+    let blacklist_match = blacklist_filter.map(|r| r.is_match(file_name)).unwrap_or(false);
+    let whitelist_match = whitelist_filter.map(|r| r.is_match(file_name)).unwrap_or(false);
+    !blacklist_match && whitelist_match
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub enum WatchEvent {
+    File(String),
+    // Kernel queue overflowed & events were lost, callers should rescan
+    Overflow,
+}
+
 #[cfg(target_os = "linux")]
 pub struct InotifyFileWatcher {
     _inotify: Inotify,
     stream: EventStream<Vec<u8>>,
-    filter: Option<Regex>,
+    blacklist_filter: Option<Regex>,
+    whitelist_filter: Option<Regex>,
 }
 
 #[cfg(target_os = "linux")]
 impl InotifyFileWatcher {
+    /// Only files matching filter are reported
     pub fn new(path: &Path, filter: Option<Regex>) -> Result<Self, Box<dyn Error>> {
+        Self::with_mask(path, WatchMask::CREATE, None, filter)
+    }
+
+    pub fn with_mask(
+        path: &Path,
+        mask: WatchMask,
+        blacklist_filter: Option<Regex>,
+        whitelist_filter: Option<Regex>,
+    ) -> Result<Self, Box<dyn Error>> {
         debug!("Creating new inotify file watcher at {:?}", path);
         let mut inotify = Inotify::init()?;
-        inotify.add_watch(path, WatchMask::CREATE)?;
+        inotify.add_watch(path, mask)?;
 
         // 32 size issue: https://github.com/hannobraun/inotify/issues/125
         let buffer = vec![0u8; 4096];
@@ -73,11 +116,21 @@ impl InotifyFileWatcher {
         Ok(Self {
             _inotify: inotify,
             stream,
-            filter,
+            blacklist_filter,
+            whitelist_filter,
         })
     }
 
     pub async fn get_new_file(&mut self) -> Option<String> {
+        loop {
+            match self.get_event().await? {
+                WatchEvent::File(file_name) => break Some(file_name),
+                WatchEvent::Overflow => error!("Inotify queue overflowed, some files might be missed"),
+            }
+        }
+    }
+
+    pub async fn get_event(&mut self) -> Option<WatchEvent> {
         let result = loop {
             let event_or_error = self.stream.next().await?;
             debug!("Received inotify event: {:?}", event_or_error);
@@ -85,9 +138,24 @@ impl InotifyFileWatcher {
                 error!("Inotify stream error: {:?}", e);
                 break None;
             } else {
-                let file_name = event_or_error.unwrap().name?.into_string().unwrap();
-                if self.filter.as_ref().map(|r| r.is_match(&file_name)) == Some(true) {
-                    break Some(file_name);
+                let event = event_or_error.unwrap();
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    break Some(WatchEvent::Overflow);
+                }
+                let file_name = match event.name.map(|name| name.into_string()) {
+                    Some(Ok(name)) => name,
+                    Some(Err(name)) => {
+                        warn!("Skipping {:?}, as file name is not valid utf-8", name);
+                        continue;
+                    }
+                    None => continue,
+                };
+                if is_filter_match(
+                    &file_name,
+                    self.blacklist_filter.as_ref(),
+                    self.whitelist_filter.as_ref(),
+                ) {
+                    break Some(WatchEvent::File(file_name));
                 }
                 debug!("Skipping {:?}, due to filter match", file_name);
                 continue;
@@ -134,17 +202,11 @@ impl FileWatcher {
         for entry in entries {
             let entry = entry?;
             if let Some(file_name) = entry.file_name().to_str() {
-                let blacklist_match = self
-                    .blacklist_filter
-                    .as_ref()
-                    .map(|r| r.is_match(&file_name))
-                    .unwrap_or(true);
-                let whitelist_match = self
-                    .whitelist_filter
-                    .as_ref()
-                    .map(|r| r.is_match(&file_name))
-                    .unwrap_or(true);
-                if !blacklist_match && whitelist_match {
+                if is_filter_match(
+                    file_name,
+                    self.blacklist_filter.as_ref(),
+                    self.whitelist_filter.as_ref(),
+                ) {
                     // This enables us to sync all files if filesystem doesnt support timestamps
                     let timestamp = entry.metadata()?.created().unwrap_or(now);
                     if self.last_sync <= timestamp {
@@ -156,12 +218,7 @@ impl FileWatcher {
                         );
                     }
                 } else {
-                    trace!(
-                        "Skipping {} (Blacklist Match: {:?} - Whitelist Match: {:?}",
-                        file_name,
-                        blacklist_match,
-                        whitelist_match
-                    );
+                    trace!("Skipping {}, due to filter match", file_name);
                 }
             }
         }
@@ -171,3 +228,25 @@ impl FileWatcher {
         Ok(new_files)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_is_filter_match() {
+        let blacklist = Regex::new(r"\.downloaded$").unwrap();
+        let whitelist = Regex::new(r"^crash-").unwrap();
+
+        assert!(is_filter_match("crash-1", Some(&blacklist), Some(&whitelist)));
+        assert!(!is_filter_match(
+            "crash-1.downloaded",
+            Some(&blacklist),
+            Some(&whitelist)
+        ));
+        assert!(!is_filter_match("leak-1", Some(&blacklist), Some(&whitelist)));
+
+        // Without whitelist nothing is wanted, without blacklist nothing is skipped
+        assert!(!is_filter_match("crash-1", Some(&blacklist), None));
+        assert!(is_filter_match("crash-1", None, Some(&whitelist)));
+    }
+}
@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+use crate::common::executors::get_volume_disk_usage;
+use crate::executor::{self, ExecutorEnum};
+use crate::models::NewWorker;
+use crate::utils::fs::which;
+
+// Fuzzers run as standalone binaries, others like libfuzzer are linked into targets
+const FUZZER_BINARIES: &[&str] = &["honggfuzz", "afl-fuzz", "radamsa"];
+const KVM_DEVICE: &str = "/dev/kvm";
+const KERNEL_RELEASE: &str = "/proc/sys/kernel/osrelease";
+
+/// Probes what this host can run, so that master doesn't place tasks here that would fail in setup
+pub async fn update_capabilities(worker: &mut NewWorker) {
+    worker.docker = Some(executor::is_usable(&ExecutorEnum::Docker).await);
+    worker.podman = Some(executor::is_usable(&ExecutorEnum::Podman).await);
+    worker.kvm = Some(Path::new(KVM_DEVICE).exists());
+    update_disk_free(worker).await;
+    worker.kernel = tokio::fs::read_to_string(KERNEL_RELEASE)
+        .await
+        .ok()
+        .map(|release| release.trim().to_owned());
+    worker.arch = Some(std::env::consts::ARCH.to_owned());
+    worker.fuzzers = FUZZER_BINARIES
+        .iter()
+        .filter(|binary| which(binary).is_some())
+        .map(|binary| binary.to_string())
+        .collect();
+    debug!("Probed worker capabilities: {:?}", worker);
+}
+
+/// Free disk keeps changing while fuzzing, so it is refreshed on every heartbeat unlike others
+pub async fn update_disk_free(worker: &mut NewWorker) {
+    worker.disk_free = match get_volume_disk_usage().await {
+        Ok((_, free)) => Some(free),
+        Err(e) => {
+            warn!("Unable to get free disk space of volume map: {}", e);
+            None
+        }
+    };
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use clap::ArgMatches;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::common::constants::{
+    FUZZY_CA_CERT, FUZZY_CLIENT_PEM, FUZZY_CONNECT_URL, WORKER_ARTIFACT_CACHE, WORKER_CGROUP_ROOT, WORKER_CONFIG,
+    WORKER_CONTAINER_VOLUME_MAP, WORKER_CORPUS_CACHE, WORKER_MIN_DISK_FREE, WORKER_OUTBOX_DIR, WORKER_RESERVED_CPUS,
+    WORKER_RESERVED_MEMORY, WORKER_SHUTDOWN_TIMEOUT,
+};
+use crate::trace::network_layer::{set_shipping_level, DEFAULT_SHIPPING_LEVEL};
+
+lazy_static! {
+    // Environment values config replaced, put back once config doesn't have those keys anymore
+    static ref REPLACED_ENV: Mutex<HashMap<&'static str, Option<String>>> = Mutex::new(HashMap::new());
+}
+
+/// Everything `fuzzy worker start` takes, so that it can be templated into a file. Keys are long
+/// arguments with underscores instead of dashes, everything is optional
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WorkerConfig {
+    pub name: Option<String>,
+
+    // Only taken from config, profiles can ask for them in their requirements
+    pub labels: Option<Vec<String>>,
+
+    pub server_url: Option<String>,
+    pub cert_authority: Option<String>,
+    pub client_identity: Option<String>,
+
+    pub volume_map: Option<String>,
+    pub cgroup_root: Option<String>,
+    pub artifact_cache: Option<String>,
+    pub corpus_cache: Option<String>,
+    pub outbox_dir: Option<String>,
+    pub min_disk_free: Option<i32>,
+
+    // Memory in MB, negative values are rejected while loading
+    pub reserved_cpus: Option<u32>,
+    pub reserved_memory: Option<u32>,
+
+    // Seconds to wait for tasks on shutdown
+    pub shutdown_timeout: Option<u64>,
+
+    // Like error, warn or info
+    pub log_shipping_level: Option<String>,
+}
+
+impl WorkerConfig {
+    /// Config file is optional, path is taken from arguments or environment
+    pub fn load(sub_matches: &ArgMatches) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = match sub_matches.value_of("config") {
+            Some(path) => path.to_owned(),
+            None => match env::var(WORKER_CONFIG) {
+                Ok(path) => path,
+                Err(_) => return Ok(None),
+            },
+        };
+        debug!("Loading worker config from {}", path);
+        let content = fs::read_to_string(Path::new(&path))?;
+        let config: WorkerConfig = serde_yaml::from_str(&content)?;
+        Ok(Some(config))
+    }
+
+    /// Settings are read from environment when not given as arguments, so exporting config there
+    /// keeps arguments in precedence over config file, which is in precedence over environment. Keys
+    /// dropped from config since last apply get their earlier environment value back
+    pub fn apply(&self) -> Result<(), Box<dyn Error>> {
+        let settings = [
+            (FUZZY_CONNECT_URL, self.server_url.clone()),
+            (FUZZY_CA_CERT, self.cert_authority.clone()),
+            (FUZZY_CLIENT_PEM, self.client_identity.clone()),
+            (WORKER_CONTAINER_VOLUME_MAP, self.volume_map.clone()),
+            (WORKER_CGROUP_ROOT, self.cgroup_root.clone()),
+            (WORKER_ARTIFACT_CACHE, self.artifact_cache.clone()),
+            (WORKER_CORPUS_CACHE, self.corpus_cache.clone()),
+            (WORKER_OUTBOX_DIR, self.outbox_dir.clone()),
+            (WORKER_MIN_DISK_FREE, self.min_disk_free.map(|m| m.to_string())),
+            (WORKER_RESERVED_CPUS, self.reserved_cpus.map(|r| r.to_string())),
+            (WORKER_RESERVED_MEMORY, self.reserved_memory.map(|r| r.to_string())),
+            (WORKER_SHUTDOWN_TIMEOUT, self.shutdown_timeout.map(|s| s.to_string())),
+        ];
+        let level = self.log_shipping_level.as_deref().unwrap_or(DEFAULT_SHIPPING_LEVEL);
+        set_shipping_level(level)?;
+
+        let mut replaced_env = REPLACED_ENV.lock().unwrap();
+        for (key, value) in settings.iter() {
+            match value {
+                Some(value) => {
+                    replaced_env.entry(*key).or_insert_with(|| env::var(key).ok());
+                    env::set_var(key, value);
+                }
+                None => match replaced_env.remove(key) {
+                    Some(Some(replaced)) => env::set_var(key, replaced),
+                    Some(None) => env::remove_var(key),
+                    None => {}
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{load_yaml, App};
+
+    use crate::common::cli::parse_disk_settings;
+    use crate::common::executors::get_min_disk_free;
+
+    #[test]
+    fn test_worker_config_precedence() {
+        let yaml = load_yaml!("../cli.yml");
+        let get_matches = |args: Vec<&str>| {
+            let matches = App::from(yaml).get_matches_from(args);
+            matches
+                .subcommand_matches("worker")
+                .unwrap()
+                .subcommand_matches("start")
+                .unwrap()
+                .clone()
+        };
+        let without_arg = get_matches(vec!["fuzzy", "worker", "start"]);
+        let with_arg = get_matches(vec!["fuzzy", "worker", "start", "--min-disk-free", "3"]);
+
+        // Config over environment
+        env::set_var(WORKER_MIN_DISK_FREE, "1");
+        let config: WorkerConfig = serde_yaml::from_str("min_disk_free: 2").unwrap();
+        config.apply().unwrap();
+        parse_disk_settings(&without_arg);
+        assert_eq!(get_min_disk_free(), 2);
+
+        // Argument over config
+        parse_disk_settings(&with_arg);
+        assert_eq!(get_min_disk_free(), 3);
+
+        // Dropping key from config brings environment back on reload
+        WorkerConfig::default().apply().unwrap();
+        parse_disk_settings(&without_arg);
+        assert_eq!(get_min_disk_free(), 1);
+        env::remove_var(WORKER_MIN_DISK_FREE);
+
+        let config: WorkerConfig = serde_yaml::from_str("labels: [team-a]").unwrap();
+        assert_eq!(config.labels, Some(vec!["team-a".to_owned()]));
+        assert!(serde_yaml::from_str::<WorkerConfig>("label: team-a").is_err());
+    }
+}
@@ -6,9 +6,12 @@ use tracing::{error, trace, warn};
 use tokio::sync::mpsc::Receiver;
 
 use crate::trace::TraceEvent;
+use crate::common::executors::{get_min_disk_free, get_volume_disk_usage};
 use crate::common::intervals::WORKER_HEARTBEAT_INTERVAL;
+use crate::common::outbox::submit_or_buffer;
 use crate::common::xpc::get_orchestrator_client;
 use crate::models::{NewSysStat, Worker};
+use crate::xpc::OutboxEntry;
 
 pub async fn heartbeat(worker: Worker, mut tracing_rx: Receiver<TraceEvent>) -> Result<(), Box<dyn std::error::Error>> {
     let worker_id = worker.id;
@@ -42,7 +45,7 @@ async fn send_trace_events(worker_id: i32, tracing_rx: &mut Receiver<TraceEvent>
             TraceEvent::NewEvent(mut e) => {
                 // Unable to get worker_id from span data, need to set it here for now
                 e.worker_id = Some(worker_id);
-                client.submit_trace_event(tonic::Request::new(e)).await?
+                submit_or_buffer(OutboxEntry::trace_event(e), Some(&mut client)).await?
             }
         };
     }
@@ -60,6 +63,23 @@ async fn send_sys_stats(worker_id: i32) -> Result<(), Box<dyn Error>> {
 
         let cpu_time = cpu::time().await?;
 
+        let (disk_total, disk_free) = match get_volume_disk_usage().await {
+            Ok((total, free)) => (Some(total), Some(free)),
+            Err(e) => {
+                warn!("Unable to get disk usage of volume map: {}", e);
+                (None, None)
+            }
+        };
+        // Scheduler goes by free disk sent with worker heartbeats, this warning is for whoever looks at logs
+        if let Some(free) = disk_free {
+            if free < get_min_disk_free() {
+                warn!(
+                    "Free disk on volume map is {} MB, corpus downloads & new tasks are paused",
+                    free
+                );
+            }
+        }
+
         let new_stat = NewSysStat {
             cpu_system_time: cpu_time.system().get::<heim::units::time::second>(),
             cpu_user_time: cpu_time.user().get::<heim::units::time::second>(),
@@ -72,6 +92,9 @@ async fn send_sys_stats(worker_id: i32) -> Result<(), Box<dyn Error>> {
             swap_used: swap.used().get::<information::megabyte>() as i32,
 
             worker_id,
+
+            disk_total,
+            disk_free,
         };
 
         let mut client = get_orchestrator_client().await?;
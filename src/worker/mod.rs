@@ -3,7 +3,7 @@ use std::fmt;
 use std::fs;
 use std::path::Path;
 
-use tokio::sync::mpsc::channel;
+use tokio::sync::{mpsc::channel, oneshot};
 use clap::ArgMatches;
 use heim::units::information;
 use tracing::{trace_span, debug, error, info, warn};
@@ -12,10 +12,24 @@ use tracing_futures::Instrument;
 use uuid::Uuid;
 
 use crate::trace::{Tracer, TraceEvent};
-use crate::common::cli::{parse_global_settings, parse_volume_map_settings};
+use crate::common::cli::{
+    parse_artifact_settings, parse_cgroup_settings, parse_corpus_cache_settings, parse_disk_settings,
+    parse_global_settings, parse_outbox_settings, parse_reservation_settings, parse_shutdown_settings,
+    parse_volume_map_settings,
+};
+use crate::common::executors::{
+    get_container_volume_map, get_reserved_cpus, get_reserved_memory, get_shutdown_timeout, set_container_volume_map,
+    set_worker_uuid,
+};
+use crate::common::intervals::WORKER_HEARTBEAT_INTERVAL;
+use crate::common::outbox::drain_outbox_forever;
+use crate::common::xpc::{get_collector_client, report_available, report_unavailable, reset_shared_channel};
 use crate::models::{NewWorker, Worker};
-use crate::xpc::collector_client::CollectorClient;
+use crate::xpc::RunningWorkerTask;
+use config::WorkerConfig;
 
+mod capabilities;
+mod config;
 mod dispatcher;
 mod tasks;
 
@@ -35,6 +49,15 @@ impl NewWorker {
                 cpus: 0,
                 memory: 0,
                 active: true,
+                docker: None,
+                podman: None,
+                kvm: None,
+                disk_free: None,
+                kernel: None,
+                arch: None,
+                fuzzers: Vec::new(),
+                labels: Vec::new(),
+                running_tasks: Vec::new(),
             };
             worker
         }
@@ -53,6 +76,14 @@ impl NewWorker {
         Ok(())
     }
 
+    // Labels replace earlier ones only if given, so that labels saved in cwd survive a restart without config
+    pub fn with_labels(mut self, labels: Option<Vec<String>>) -> Self {
+        if let Some(labels) = labels {
+            self.labels = labels;
+        }
+        self
+    }
+
     // Assign given name to this worker
     pub fn with_name(mut self, name: Option<&str>) -> Self {
         if let Some(custom_name) = name {
@@ -71,12 +102,12 @@ impl NewWorker {
     }
 
     pub async fn update_self(&mut self) {
-        // Update CPU
+        // Update CPU, reserved ones are left out so that scheduler never allocates them
         let cpus = heim::cpu::logical_count().await;
         if let Err(e) = cpus {
             panic!("Failed to get cpu count: {}", e);
         } else {
-            self.cpus = cpus.unwrap() as i32;
+            self.cpus = get_advertised(cpus.unwrap() as i32, get_reserved_cpus(), "cpus");
         }
 
         // Update Memory
@@ -84,22 +115,45 @@ impl NewWorker {
         if let Err(e) = memory {
             panic!("Failed to get memory: {}", e);
         } else {
-            self.memory = memory.unwrap().total().get::<information::megabyte>() as i32;
+            let memory = memory.unwrap().total().get::<information::megabyte>() as i32;
+            self.memory = get_advertised(memory, get_reserved_memory(), "memory");
         }
+
+        // Update capabilities
+        capabilities::update_capabilities(self).await;
     }
 
-    pub async fn get_worker_info(&self) -> Result<Worker, Box<dyn Error>> {
-        let endpoint = crate::common::xpc::get_server_endpoint().await?;
-        let channel = endpoint.connect().await?;
-        // Send heartbeat
-        let mut client = CollectorClient::new(channel);
+    /// Sends heartbeat along with worker tasks actually running, for master to reconcile against
+    pub async fn get_worker_info(&mut self, running_tasks: Vec<RunningWorkerTask>) -> Result<Worker, Box<dyn Error>> {
+        capabilities::update_disk_free(self).await;
+        self.running_tasks = running_tasks;
+        let mut client = get_collector_client().await?;
         let request = tonic::Request::new(self.clone());
-        let response = client.heartbeat(request).await?;
+        let response = match client.heartbeat(request).await {
+            Ok(response) => response,
+            Err(status) => {
+                report_unavailable(&status).await;
+                return Err(Box::new(status));
+            }
+        };
+        report_available().await;
         let worker = response.into_inner();
         Ok(worker)
     }
 }
 
+fn get_advertised(detected: i32, reserved: i32, resource: &str) -> i32 {
+    if reserved >= detected {
+        warn!(
+            "Reserved {} {} is not less than detected {}, advertising none",
+            reserved, resource, detected
+        );
+        0
+    } else {
+        detected - reserved
+    }
+}
+
 impl fmt::Display for NewWorker {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // This ugly thing has to done for proper string formatting
@@ -110,12 +164,70 @@ impl fmt::Display for NewWorker {
     }
 }
 
+// Settings that can change on reload, volume map can't as running tasks have their directories on it
+fn parse_reloadable_settings(sub_matches: &ArgMatches) {
+    parse_global_settings(sub_matches);
+    parse_cgroup_settings(sub_matches);
+    parse_artifact_settings(sub_matches);
+    parse_corpus_cache_settings(sub_matches);
+    parse_outbox_settings(sub_matches);
+    parse_disk_settings(sub_matches);
+    parse_reservation_settings(sub_matches);
+    parse_shutdown_settings(sub_matches);
+}
+
+/// Re-reads worker config on SIGHUP, arguments still take precedence over it
+async fn reload_config(
+    sub_matches: &ArgMatches,
+    new_worker: &mut NewWorker,
+    running_tasks: Vec<RunningWorkerTask>,
+) -> Result<(), Box<dyn Error>> {
+    let config = match WorkerConfig::load(sub_matches)? {
+        Some(config) => config,
+        None => {
+            warn!("No worker config to reload");
+            return Ok(());
+        }
+    };
+
+    let volume_map = get_container_volume_map()?;
+    config.apply()?;
+    if config.volume_map.as_ref().map(|v| v != &volume_map).unwrap_or(false) {
+        warn!("Volume map can't change without a restart, keeping {}", volume_map);
+    }
+    set_container_volume_map(&volume_map);
+    parse_reloadable_settings(sub_matches);
+
+    // Server url or certificates might have changed
+    reset_shared_channel().await;
+
+    if sub_matches.is_present("name") == false && config.name.is_some() {
+        new_worker.name = config.name;
+    }
+    if let Some(labels) = config.labels {
+        new_worker.labels = labels;
+    }
+    if let Err(e) = new_worker.save_to_cwd() {
+        error!("Failed to save metadata to cwd: {}", e);
+    }
+
+    // Let master know about changed name & labels
+    new_worker.update_self().await;
+    new_worker.get_worker_info(running_tasks).await?;
+    info!("Reloaded worker config");
+    Ok(())
+}
+
 #[tokio::main]
-pub async fn main_loop(mut new_worker: NewWorker, tracer: Tracer) -> Result<(), Box<dyn Error>> {
+pub async fn main_loop(
+    mut new_worker: NewWorker,
+    tracer: Tracer,
+    sub_matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
     // Launch a cpu update task, because of well `heim` and async only
     // Then get worker info struct
     new_worker.update_self().await;
-    let worker = new_worker.get_worker_info().await?;
+    let worker = new_worker.get_worker_info(Vec::new()).await?;
 
     // Setup global logging writes to TraceEvent channel
     let (tx, rx) = channel::<TraceEvent>(50);
@@ -125,41 +237,104 @@ pub async fn main_loop(mut new_worker: NewWorker, tracer: Tracer) -> Result<(),
     // Launch periodic heartbeat dispatcher
     info!("Launching heartbeat task");
     let worker_clone = worker.clone();
-    let heartbeat_handle = tokio::spawn(async move {
+    let mut heartbeat_handle = tokio::spawn(async move {
         if let Err(e) = dispatcher::heartbeat(worker_clone, rx).await {
             error!("Dispatcher exited with error: {}", e);
         }
     });
 
+    // Buffered uploads are drained in background, nothing to wait on as it never exits
+    info!("Launching outbox drain task");
+    tokio::spawn(drain_outbox_forever());
+
     // Set worker.id
     let span = trace_span!("worker", worker_id=worker.id);
     let _guard = span.enter();
 
+    // Worker tasks are restarted with same cwd & container names, so leftovers of a crashed run have to go
+    // first. Crashes & corpus of tasks are uploaded before their cwd is cleaned up
+    match crate::fuzz_driver::leftovers::salvage_tasks().await {
+        Ok(salvaged) if salvaged > 0 => info!("Salvaged {} worker tasks of earlier run", salvaged),
+        Ok(_) => {}
+        Err(e) => error!("Unable to salvage worker tasks of earlier run: {}", e),
+    }
+    if let Err(e) = crate::executor::remove_orphaned_containers().await {
+        error!("Unable to remove orphaned containers: {}", e);
+    }
+
     // Launch task manager
-    let mut task_manager = tasks::TaskManager::new();
+    let mut task_manager = tasks::TaskManager::new(worker.cpus as usize);
+    let running_rx = task_manager.get_running_receiver();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<u8>();
     info!("Launching task manager task");
-    let task_manager_handle = tokio::spawn(async move {
-        if let Err(e) = task_manager.spawn(worker).in_current_span().await {
+    let mut task_manager_handle = tokio::spawn(async move {
+        if let Err(e) = task_manager.spawn(worker, shutdown_rx).in_current_span().await {
             error!("Task manager exited with error: {}", e);
         }
     });
 
-    // Listen for SIGINT
-    let mut stream = signal(SignalKind::interrupt())?;
-    tokio::select! {
-        result = heartbeat_handle => {
-            if let Err(e) = result {
-                error!("Heartbeat handle exited first: {}", e);
-            }
-        },
-        result = task_manager_handle => {
-            if let Err(e) = result {
-                error!("Task manager exited first: {}", e);
-            }
-        },
-        _ = stream.recv() => {
-            info!("Keyboard interrput received");
-        },
+    // Listen for SIGINT & SIGTERM (systemd, docker stop), both shut down gracefully. SIGHUP reloads config
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    // Keeps master's view of worker tasks in line with what is actually running here
+    let mut heartbeat_interval = tokio::time::interval(WORKER_HEARTBEAT_INTERVAL);
+    loop {
+        tokio::select! {
+            result = &mut heartbeat_handle => {
+                if let Err(e) = result {
+                    error!("Heartbeat handle exited first: {}", e);
+                }
+                break;
+            },
+            result = &mut task_manager_handle => {
+                if let Err(e) = result {
+                    error!("Task manager exited first: {}", e);
+                }
+                break;
+            },
+            _ = interrupt.recv() => {
+                info!("Keyboard interrput received");
+                break;
+            },
+            _ = terminate.recv() => {
+                info!("Terminate signal received");
+                break;
+            },
+            _ = hangup.recv() => {
+                info!("Hangup signal received, reloading config");
+                let running_tasks = tasks::get_running_tasks(&running_rx);
+                if let Err(e) = reload_config(sub_matches, &mut new_worker, running_tasks).await {
+                    error!("Unable to reload worker config, continuing with old one: {}", e);
+                }
+            },
+            _ = heartbeat_interval.tick() => {
+                let running_tasks = tasks::get_running_tasks(&running_rx);
+                if let Err(e) = new_worker.get_worker_info(running_tasks).await {
+                    warn!("Unable to send heartbeat to master: {}", e);
+                }
+            },
+        }
+    }
+
+    // Let master know we are leaving before waiting on tasks, so that it is done even if whatever stops
+    // us runs out of patience. Nothing is scheduled here till we are back
+    new_worker.active = false;
+    let running_tasks = tasks::get_running_tasks(&running_rx);
+    if let Err(e) = new_worker.get_worker_info(running_tasks).await {
+        error!("Unable to inform master about leaving: {}", e);
+    }
+
+    // Send fails if task manager already exited, in which case there is nothing to wait on
+    if shutdown_tx.send(0).is_ok() {
+        let shutdown_timeout = get_shutdown_timeout();
+        info!("Waiting {:?} for worker tasks to stop", shutdown_timeout);
+        if tokio::time::timeout(shutdown_timeout, task_manager_handle)
+            .await
+            .is_err()
+        {
+            error!("Worker tasks didn't stop in {:?}, exiting anyway", shutdown_timeout);
+        }
     }
 
     Ok(())
@@ -172,19 +347,33 @@ pub fn main(arg_matches: &ArgMatches, tracer: Tracer) {
     match arg_matches.subcommand() {
         ("start", Some(sub_matches)) => {
             info!("Starting worker agent");
+            let config = match WorkerConfig::load(sub_matches) {
+                Ok(config) => config.unwrap_or_default(),
+                Err(e) => {
+                    error!("Unable to load worker config: {}", e);
+                    panic!("Exiting");
+                }
+            };
+            if let Err(e) = config.apply() {
+                error!("Invalid worker config: {}", e);
+                panic!("Exiting");
+            }
+
             let w = NewWorker::new()
                 .with_uuid(sub_matches.value_of("uuid"))
-                .with_name(sub_matches.value_of("name"));
+                .with_name(sub_matches.value_of("name").or(config.name.as_deref()))
+                .with_labels(config.labels);
 
             if let Err(e) = w.save_to_cwd() {
                 error!("Failed to save metadata to cwd: {}", e);
             }
+            set_worker_uuid(&w.uuid);
 
-            parse_global_settings(sub_matches);
             parse_volume_map_settings(sub_matches);
+            parse_reloadable_settings(sub_matches);
 
             // Start main loop
-            if let Err(e) = main_loop(w, tracer) {
+            if let Err(e) = main_loop(w, tracer, sub_matches) {
                 error!("{}", e);
                 panic!("Failed to start main loop")
             }
@@ -192,3 +381,17 @@ pub fn main(arg_matches: &ArgMatches, tracer: Tracer) {
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_get_advertised() {
+        assert_eq!(get_advertised(16, 0, "cpus"), 16);
+        assert_eq!(get_advertised(16, 2, "cpus"), 14);
+        assert_eq!(get_advertised(16, 15, "cpus"), 1);
+        // Reserving everything or more leaves nothing to schedule
+        assert_eq!(get_advertised(16, 16, "cpus"), 0);
+        assert_eq!(get_advertised(8192, 16384, "memory"), 0);
+    }
+}
@@ -4,34 +4,107 @@ use std::error::Error;
 use tracing::{debug, error, info, trace, warn};
 use tokio::{
     sync::oneshot::{self, error::TryRecvError},
+    sync::watch,
     task::JoinHandle,
 };
 
+use crate::common::executors::is_disk_low;
 use crate::common::intervals::WORKER_TASK_REFRESH_INTERVAL;
-use crate::fuzz_driver::{self, FuzzConfig};
+use crate::common::xpc::{get_orchestrator_client, report_unavailable};
+use crate::fuzz_driver::{self, DriverState, FuzzConfig};
 use crate::worker::Worker;
 use crate::xpc;
-use crate::xpc::orchestrator_client::OrchestratorClient;
+
+/// Driver state of every worker task being run, read for each heartbeat
+pub type RunningReceiver = watch::Receiver<Vec<(i32, watch::Receiver<DriverState>)>>;
 
 struct TaskManagerTask {
     task_updated_at: prost_types::Timestamp,
     driver_handle: JoinHandle<()>,
     kill_switch: oneshot::Sender<u8>,
     dead_switch: oneshot::Receiver<u8>,
+    state: watch::Receiver<DriverState>,
 }
 
 pub struct TaskManager {
     tasks: HashMap<i32, TaskManagerTask>,
+    // Usable cores along with worker task pinned to each
+    cores: Vec<(usize, Option<i32>)>,
+    running_tx: watch::Sender<Vec<(i32, watch::Receiver<DriverState>)>>,
+    running_rx: RunningReceiver,
 }
 
 impl TaskManager {
-    pub fn new() -> Self {
-        Self { tasks: HashMap::new() }
+    pub fn new(cpus: usize) -> Self {
+        Self::with_cores(get_usable_cores(cpus))
+    }
+
+    fn with_cores(cores: Vec<usize>) -> Self {
+        let (running_tx, running_rx) = watch::channel(Vec::new());
+        Self {
+            tasks: HashMap::new(),
+            cores: cores.into_iter().map(|core| (core, None)).collect(),
+            running_tx,
+            running_rx,
+        }
+    }
+
+    /// Worker tasks actually running, updated on every refresh. Use get_running_tasks for their state
+    pub fn get_running_receiver(&self) -> RunningReceiver {
+        self.running_rx.clone()
+    }
+
+    fn publish_running_tasks(&self) {
+        let running_tasks = self
+            .tasks
+            .iter()
+            .map(|(worker_task_id, wtask)| (*worker_task_id, wtask.state.clone()))
+            .collect();
+        if let Err(e) = self.running_tx.broadcast(running_tasks) {
+            debug!("Nobody is listening for running tasks: {}", e);
+        }
+    }
+
+    /// Reserves requested number of free cores for worker task, none if not enough are free in
+    /// which case task runs unpinned rather than not at all
+    fn assign_cores(&mut self, worker_task_id: i32, count: usize) -> Option<Vec<usize>> {
+        let free: Vec<usize> = self
+            .cores
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, assigned))| assigned.is_none())
+            .map(|(i, _)| i)
+            .take(count)
+            .collect();
+
+        if count == 0 || free.len() < count {
+            warn!(
+                "Unable to find {} free cores for worker task {}, running unpinned",
+                count, worker_task_id
+            );
+            return None;
+        }
+        let mut assigned = Vec::new();
+        for i in free.into_iter() {
+            self.cores[i].1 = Some(worker_task_id);
+            assigned.push(self.cores[i].0);
+        }
+        debug!("Assigned cores {:?} to worker task {}", assigned, worker_task_id);
+        Some(assigned)
+    }
+
+    fn release_cores(&mut self, worker_task_id: i32) {
+        for (_, assigned) in self.cores.iter_mut() {
+            if *assigned == Some(worker_task_id) {
+                *assigned = None;
+            }
+        }
     }
 
     async fn remove_worker_task(&mut self, worker_task_id: &i32) -> Result<(), Box<dyn Error>> {
         debug!("Removing worker task: {:?}", worker_task_id);
         let wtask = self.tasks.remove(worker_task_id).unwrap();
+        self.release_cores(*worker_task_id);
 
         debug!("Sending kill command");
         let _ = wtask.kill_switch.send(0u8);
@@ -40,6 +113,26 @@ impl TaskManager {
         Ok(())
     }
 
+    /// Kills all drivers together & waits on them, each does its final corpus sync and marks its worker
+    /// task inactive before exiting
+    async fn remove_all_worker_tasks(&mut self) {
+        let mut driver_handles = Vec::new();
+        for (worker_task_id, wtask) in self.tasks.drain() {
+            debug!("Sending kill command to worker task {}", worker_task_id);
+            let _ = wtask.kill_switch.send(0u8);
+            driver_handles.push((worker_task_id, wtask.driver_handle));
+        }
+        for (_, assigned) in self.cores.iter_mut() {
+            *assigned = None;
+        }
+
+        for (worker_task_id, driver_handle) in driver_handles {
+            if let Err(e) = driver_handle.await {
+                error!("Driver of worker task {} didn't exit cleanly: {}", worker_task_id, e);
+            }
+        }
+    }
+
     async fn check_worker_task(&mut self, worker_task_id: &i32) -> Result<(), Box<dyn Error>> {
         debug!("Checking worker task: {:?}", worker_task_id);
         if let Some(wtask) = self.tasks.get_mut(worker_task_id) {
@@ -58,15 +151,17 @@ impl TaskManager {
 
     async fn add_worker_task(&mut self, wtask: xpc::WorkerTaskFull) -> Result<(), Box<dyn Error>> {
         debug!("Adding worker task: {:?}", wtask);
-        let profile: FuzzConfig = serde_yaml::from_str(wtask.task.profile.as_str())?;
+        let mut profile: FuzzConfig = serde_yaml::from_str(wtask.task.profile.as_str())?;
+        profile.execution.cpuset = self.assign_cores(wtask.id, profile.execution.cpus as usize);
         let (tx, rx) = oneshot::channel::<u8>();
         let (dead_tx, dead_rx) = oneshot::channel::<u8>();
+        let (state_tx, state_rx) = watch::channel(DriverState::default());
         let mut driver = fuzz_driver::new(profile, Some(wtask.id));
 
         info!("Spawning new task: {:#?}", wtask);
 
         let driver_handle = tokio::spawn(async move {
-            if let Err(e) = driver.start(rx, dead_tx).await {
+            if let Err(e) = driver.start(rx, dead_tx, state_tx).await {
                 error!("Driver exited with error: {}", e);
             }
         });
@@ -77,6 +172,7 @@ impl TaskManager {
                 driver_handle,
                 kill_switch: tx,
                 dead_switch: dead_rx,
+                state: state_rx,
                 task_updated_at: wtask.task.updated_at,
             },
         );
@@ -89,6 +185,8 @@ impl TaskManager {
     /// 2. Start an active task if we are not running. (Stale tasks are handled above)
     async fn handle_tasks_update(&mut self, worker_tasks: Vec<xpc::WorkerTaskFull>) -> Result<(), Box<dyn Error>> {
         trace!("Handling task updates, iterating over {} tasks", worker_tasks.len());
+        // Running tasks are left alone, only new ones are held back till space frees up
+        let disk_low = is_disk_low().await;
         for worker_task in worker_tasks.into_iter() {
             let global_task_active = worker_task.active;
 
@@ -113,6 +211,10 @@ impl TaskManager {
             }
 
             if self.tasks.contains_key(&worker_task.id) == false && global_task_active == true {
+                if disk_low {
+                    warn!("Not starting worker task {} as disk space is low", worker_task.id);
+                    continue;
+                }
                 // Start this as we should be running it
                 self.add_worker_task(worker_task).await?;
             }
@@ -120,14 +222,17 @@ impl TaskManager {
         Ok(())
     }
 
-    pub async fn spawn(&mut self, worker: Worker) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn spawn(
+        &mut self,
+        worker: Worker,
+        mut shutdown: oneshot::Receiver<u8>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let mut interval = tokio::time::interval(WORKER_TASK_REFRESH_INTERVAL);
         loop {
             trace!("Trying to get tasks and update");
-            // TODO: Fix this later, unable to send future error
-            let endpoint = crate::common::xpc::get_server_endpoint().await?;
-            if let Ok(channel) = endpoint.connect().await {
-                let mut client = OrchestratorClient::new(channel);
+            // Error is dropped right away, as Box<dyn Error> can't be held across awaits of a spawned future
+            let client = get_orchestrator_client().await.map_err(|e| e.to_string());
+            if let Ok(mut client) = client {
                 // Create new filter request
                 let worker_clone = worker.clone();
                 let filter_worker_task = xpc::FilterWorkerTask {
@@ -137,6 +242,7 @@ impl TaskManager {
 
                 let response = client.get_worker_task(filter_worker_task).await;
                 if let Err(e) = response {
+                    report_unavailable(&e).await;
                     error!("Getting worker task failed: {}", e);
                 } else {
                     let worker_tasks = response.unwrap().into_inner();
@@ -147,7 +253,97 @@ impl TaskManager {
             } else {
                 warn!("Failed to get tasks, will try after {:?}", WORKER_TASK_REFRESH_INTERVAL);
             }
-            interval.tick().await;
+            self.publish_running_tasks();
+
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = &mut shutdown => {
+                    info!("Shutting down {} worker tasks", self.tasks.len());
+                    self.remove_all_worker_tasks().await;
+                    self.publish_running_tasks();
+                    return Ok(());
+                },
+            }
         }
     }
 }
+
+/// State of worker tasks as of now, drivers publish process & restarts as they change them
+pub fn get_running_tasks(running_rx: &RunningReceiver) -> Vec<xpc::RunningWorkerTask> {
+    running_rx
+        .borrow()
+        .iter()
+        .map(|(worker_task_id, state)| {
+            let state = state.borrow();
+            xpc::RunningWorkerTask {
+                worker_task_id: *worker_task_id,
+                process: state.process.clone(),
+                restarts: state.restarts,
+            }
+        })
+        .collect()
+}
+
+/// Cores we are allowed to run on, which needn't be 0..cpus under a cpuset or with offline cpus.
+/// Kernel reports affinity mask of our process (sched_getaffinity) as a list in status
+fn get_usable_cores(cpus: usize) -> Vec<usize> {
+    let allowed = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find(|line| line.starts_with("Cpus_allowed_list:"))
+                .map(|line| line["Cpus_allowed_list:".len()..].to_owned())
+        })
+        .or_else(|| std::fs::read_to_string("/sys/devices/system/cpu/online").ok());
+
+    let mut cores = allowed
+        .and_then(|list| parse_cpu_list(&list))
+        .unwrap_or_else(|| (0..cpus).collect());
+    cores.truncate(cpus);
+    cores
+}
+
+/// Parses kernel's cpu list format like 0-3,8,10-11
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cores = Vec::new();
+    for range in list.trim().split(',').filter(|range| range.is_empty() == false) {
+        let mut bounds = range.splitn(2, '-');
+        let start = bounds.next()?.trim().parse::<usize>().ok()?;
+        let end = match bounds.next() {
+            Some(end) => end.trim().parse::<usize>().ok()?,
+            None => start,
+        };
+        cores.extend(start..=end);
+    }
+    if cores.is_empty() {
+        None
+    } else {
+        Some(cores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3\n"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(parse_cpu_list("\t2,4-5,8"), Some(vec![2, 4, 5, 8]));
+        assert_eq!(parse_cpu_list(""), None);
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn test_assign_cores() {
+        let mut task_manager = TaskManager::with_cores(vec![2, 3, 6]);
+        assert_eq!(task_manager.assign_cores(1, 2), Some(vec![2, 3]));
+        // Not enough free cores left, so task runs unpinned
+        assert_eq!(task_manager.assign_cores(2, 2), None);
+        assert_eq!(task_manager.assign_cores(2, 0), None);
+        assert_eq!(task_manager.assign_cores(3, 1), Some(vec![6]));
+
+        task_manager.release_cores(1);
+        assert_eq!(task_manager.assign_cores(2, 2), Some(vec![2, 3]));
+    }
+}
@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use diesel::{AsChangeset, Identifiable, Insertable, Queryable};
 
 // Insert table names
-use super::schema::{corpora, crashes, fuzz_stats, sys_stats, tasks, worker_tasks, workers, trace_events};
+use super::schema::{
+    artifacts, corpora, corpus_snapshots, crash_variants, crashes, fuzz_stats, sys_stats, tasks, worker_task_logs,
+    worker_tasks, workers, trace_events,
+};
 
 tonic::include_proto!("xpc"); // The string specified here must match the proto package name